@@ -1,18 +1,94 @@
 use std::env;
+use std::io::Read;
+use std::sync::OnceLock;
 
+use anyhow::{Context, Result};
 use clap::error::ErrorKind;
-use clap::{ArgGroup, Command, CommandFactory, Parser};
+use clap::{ArgGroup, Command, CommandFactory, Parser, Subcommand};
 use dotenv::dotenv;
 
+use crate::client_auth_method::ClientAuthMethod;
 use crate::config_file::ConfigFile;
 use crate::grant::Grant;
+use crate::output::Output;
+use crate::signing_algorithm::SigningAlgorithm;
+
+pub const DEFAULT_CLOCK_SKEW: u64 = 30;
+pub const DEFAULT_SCOPE: &str = "offline_access";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// `DOKEN_*` variables doken was started with, before `--profile` was applied to them
+static STARTUP_ENV: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+fn doken_env() -> Vec<(String, String)> {
+    env::vars()
+        .filter(|(name, _)| name.starts_with("DOKEN_"))
+        .collect()
+}
+
+fn replace_doken_env(vars: &[(String, String)]) {
+    for (name, _) in doken_env() {
+        unsafe { env::remove_var(name) }
+    }
+
+    for (name, value) in vars {
+        unsafe { env::set_var(name, value) }
+    }
+}
+
+/// Operations other than printing a token
+#[derive(Subcommand, Debug, Clone)]
+pub enum Operation {
+    /// Prints a DPoP proof of a request made with the token. Requires `--dpop` <https://www.rfc-editor.org/rfc/rfc9449#section-7>
+    DpopProof {
+        /// HTTP method of the request
+        #[clap(long, default_value = "GET")]
+        method: String,
+
+        /// Url of the request
+        #[clap(long)]
+        url: String,
+
+        /// Nonce given by the resource server in `DPoP-Nonce` header
+        #[clap(long)]
+        nonce: Option<String>,
+    },
+
+    /// Revokes the cached refresh and access tokens and removes them from the state <https://www.rfc-editor.org/rfc/rfc7009>
+    Revoke {
+        /// Revokes all of the tokens cached for the client (any scope, grant or audience) instead of the ones matching the arguments. Tokens of other clients are skipped
+        #[clap(long, action, default_value_t = false)]
+        all: bool,
+    },
+
+    /// Prints claims about the logged in user given by the IdP's UserInfo endpoint as JSON <https://openid.net/specs/openid-connect-core-1_0.html#UserInfo>
+    Userinfo,
+
+    /// Prints header and claims of the cached token (selected with `--output`) decoded locally <https://www.rfc-editor.org/rfc/rfc7519>
+    Inspect {
+        /// Token to inspect from standard input instead of the cached one
+        #[clap(long, action, default_value_t = false)]
+        token_stdin: bool,
+
+        /// Verifies the signature with the IdP's JWKS
+        #[clap(long, action, default_value_t = false)]
+        verify: bool,
+    },
+
+    /// Prints the IdP's introspection response of the cached token as JSON <https://www.rfc-editor.org/rfc/rfc7662>
+    Introspect {
+        /// Token to introspect instead of the cached one
+        #[clap(long)]
+        token: Option<String>,
+    },
+}
 
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about)]
 #[clap(group(
     ArgGroup::new("oauth2")
         .multiple(true)
-        .args(["token_url", "authorization_url"])
+        .args(["token_url", "authorization_url", "issuer"])
         .conflicts_with("oidc")
 ))]
 #[clap(group(
@@ -33,6 +109,47 @@ pub struct Arguments {
     #[clap(long, env = "DOKEN_AUTHORIZATION_URL")]
     pub authorization_url: Option<String>,
 
+    /// OAuth 2.0 device authorization url <https://www.rfc-editor.org/rfc/rfc8628#section-3.1>. Takes precedence over the discovered one
+    #[clap(long, env = "DOKEN_DEVICE_AUTHORIZATION_URL")]
+    pub device_authorization_url: Option<String>,
+
+    /// OAuth 2.0 pushed authorization request url <https://www.rfc-editor.org/rfc/rfc9126#section-2>. Takes precedence over the discovered one
+    #[clap(long, env = "DOKEN_PUSHED_AUTHORIZATION_REQUEST_URL")]
+    pub pushed_authorization_request_url: Option<String>,
+
+    /// OpenID Connect issuer identifier checked in ID tokens <https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation>
+    #[clap(long, env = "DOKEN_ISSUER")]
+    pub issuer: Option<String>,
+
+    /// JSON Web Key Set url of keys signing ID tokens <https://www.rfc-editor.org/rfc/rfc7517#section-5>. Takes precedence over the discovered one
+    #[clap(long, env = "DOKEN_JWKS_URL")]
+    pub jwks_url: Option<String>,
+
+    /// Accepts ID tokens without validating them, e.g. when the IdP's issuer or JWKS url is unknown
+    #[clap(
+        long,
+        action,
+        default_value_t = false,
+        env = "DOKEN_SKIP_ID_TOKEN_VALIDATION"
+    )]
+    pub skip_id_token_validation: bool,
+
+    /// OpenID Connect UserInfo url <https://openid.net/specs/openid-connect-core-1_0.html#UserInfo>. Takes precedence over the discovered one
+    #[clap(long, env = "DOKEN_USERINFO_URL")]
+    pub userinfo_url: Option<String>,
+
+    /// OAuth 2.0 token revocation url <https://www.rfc-editor.org/rfc/rfc7009#section-2>. Takes precedence over the discovered one
+    #[clap(long, env = "DOKEN_REVOCATION_URL")]
+    pub revocation_url: Option<String>,
+
+    /// OAuth 2.0 token introspection url <https://www.rfc-editor.org/rfc/rfc7662#section-2>. Takes precedence over the discovered one
+    #[clap(long, env = "DOKEN_INTROSPECTION_URL")]
+    pub introspection_url: Option<String>,
+
+    /// Pushes parameters of Authorization Code grants before opening a browser. Always on when required by discovery <https://www.rfc-editor.org/rfc/rfc9126>
+    #[clap(long, action, default_value_t = false, env = "DOKEN_PAR")]
+    pub par: bool,
+
     /// OpenID Connect discovery url
     #[clap(long, env = "DOKEN_DISCOVERY_URL")]
     pub discovery_url: Option<String>,
@@ -41,6 +158,53 @@ pub struct Arguments {
     #[clap(long, env = "DOKEN_CALLBACK_URL")]
     pub callback_url: Option<String>,
 
+    /// Opens the system browser and listens on loopback `--callback-url` instead of using Chromium. Without a port in the url an ephemeral one is used <https://www.rfc-editor.org/rfc/rfc8252#section-7.3>
+    #[clap(long, action, default_value_t = false, env = "DOKEN_SYSTEM_BROWSER")]
+    pub system_browser: bool,
+
+    /// Prints the authorization url and reads the url you were redirected to (or the code, with PKCE) pasted back from the terminal. Useful on hosts without any browser
+    #[clap(
+        long,
+        action,
+        default_value_t = false,
+        env = "DOKEN_NO_BROWSER",
+        conflicts_with = "system_browser"
+    )]
+    pub no_browser: bool,
+
+    /// Time in milliseconds to paste the redirect back with `--no-browser`. It's longer than `--timeout`, as authorizing on another device takes a while
+    #[clap(long, default_value_t = 300_000, env = "DOKEN_NO_BROWSER_TIMEOUT")]
+    pub no_browser_timeout: u64,
+
+    /// Browser profile directory keeping IdP sessions between runs [default: ~/.doken/browser-profile]
+    #[clap(long, env = "DOKEN_BROWSER_PROFILE_DIR")]
+    pub browser_profile_dir: Option<String>,
+
+    /// Path of a Chromium-based browser executable used instead of the detected one
+    #[clap(long, env = "DOKEN_BROWSER_EXECUTABLE")]
+    pub browser_executable: Option<String>,
+
+    /// Extra arguments the browser is launched with separated by spaces ex. `--browser-args "--lang=en --disable-gpu"`
+    #[clap(
+        long,
+        env = "DOKEN_BROWSER_ARGS",
+        value_delimiter = ' ',
+        allow_hyphen_values = true
+    )]
+    pub browser_args: Vec<String>,
+
+    /// DevTools websocket url of a running browser, in which a new tab is opened instead of launching a browser. Falls back to launching when it can't be reached
+    #[clap(
+        long,
+        env = "DOKEN_BROWSER_WS_URL",
+        conflicts_with = "browser_debug_port"
+    )]
+    pub browser_ws_url: Option<String>,
+
+    /// Remote debugging port of a running browser started with `--remote-debugging-port`, in which a new tab is opened instead of launching a browser. Falls back to launching when nothing listens on it
+    #[clap(long, env = "DOKEN_BROWSER_DEBUG_PORT")]
+    pub browser_debug_port: Option<u16>,
+
     /// OAuth 2.0 Client Identifier <https://www.rfc-editor.org/rfc/rfc6749#section-2.2>
     #[clap(long, env = "DOKEN_CLIENT_ID")]
     pub client_id: String,
@@ -53,6 +217,34 @@ pub struct Arguments {
     #[clap(long, action, default_value_t = false)]
     pub client_secret_stdin: bool,
 
+    /// Client authentication method used while calling the token endpoint <https://openid.net/specs/openid-connect-core-1_0.html#ClientAuthentication>
+    #[clap(long, value_enum, default_value_t = ClientAuthMethod::ClientSecretBasic, env = "DOKEN_CLIENT_AUTH_METHOD")]
+    pub client_auth_method: ClientAuthMethod,
+
+    /// PEM private key file signing client assertions of `private_key_jwt` client authentication method
+    #[clap(long, env = "DOKEN_CLIENT_ASSERTION_KEY")]
+    pub client_assertion_key: Option<String>,
+
+    /// Client assertion signing algorithm of `private_key_jwt` client authentication method
+    #[clap(long, value_enum, default_value_t = SigningAlgorithm::Rs256, env = "DOKEN_CLIENT_ASSERTION_ALGORITHM")]
+    pub client_assertion_algorithm: SigningAlgorithm,
+
+    /// Client assertion `kid` header of `private_key_jwt` and `client_secret_jwt` client authentication methods
+    #[clap(long, env = "DOKEN_CLIENT_ASSERTION_KEY_ID")]
+    pub client_assertion_key_id: Option<String>,
+
+    /// Client certificate file (PEM or PKCS#12) used for Mutual-TLS <https://www.rfc-editor.org/rfc/rfc8705>
+    #[clap(long, env = "DOKEN_CLIENT_CERT")]
+    pub client_cert: Option<String>,
+
+    /// PEM private key file of `--client-cert`. Not needed when the key is a part of `--client-cert` file
+    #[clap(long, requires = "client_cert", env = "DOKEN_CLIENT_KEY")]
+    pub client_key: Option<String>,
+
+    /// Password of PKCS#12 `--client-cert` file
+    #[clap(long, requires = "client_cert", env = "DOKEN_CLIENT_CERT_PASSWORD")]
+    pub client_cert_password: Option<String>,
+
     /// OAuth 2.0 Resource Owner Password Client Credentials Grant's username <https://www.rfc-editor.org/rfc/rfc6749#section-4.3.2>
     #[clap(short, long, env = "DOKEN_USERNAME")]
     pub username: Option<String>,
@@ -66,17 +258,113 @@ pub struct Arguments {
     pub password_stdin: bool,
 
     /// OAuth 2.0 Scope <https://www.rfc-editor.org/rfc/rfc6749#section-3.3>
-    #[clap(long, default_value = "offline_access", env = "DOKEN_SCOPE")]
+    #[clap(long, default_value = DEFAULT_SCOPE, env = "DOKEN_SCOPE")]
     pub scope: String,
 
     /// OpenID Connect requested aud
     #[clap(long, env = "DOKEN_AUDIENCE")]
     pub audience: Option<String>,
 
+    /// OAuth 2.0 Token Exchange Grant's subject token <https://www.rfc-editor.org/rfc/rfc8693#section-2.1>
+    #[clap(long, env = "DOKEN_SUBJECT_TOKEN")]
+    pub subject_token: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's subject token from standard input <https://www.rfc-editor.org/rfc/rfc8693#section-2.1>
+    #[clap(long, action, default_value_t = false)]
+    pub subject_token_stdin: bool,
+
+    /// Profile whose token cached by doken is used as OAuth 2.0 Token Exchange Grant's subject token
+    #[clap(long, env = "DOKEN_SUBJECT_TOKEN_PROFILE")]
+    pub subject_token_profile: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's subject token type <https://www.rfc-editor.org/rfc/rfc8693#section-3>
+    #[clap(long, default_value = ACCESS_TOKEN_TYPE, env = "DOKEN_SUBJECT_TOKEN_TYPE")]
+    pub subject_token_type: String,
+
+    /// OAuth 2.0 Token Exchange Grant's actor token <https://www.rfc-editor.org/rfc/rfc8693#section-2.1>
+    #[clap(long, requires = "actor_token_type", env = "DOKEN_ACTOR_TOKEN")]
+    pub actor_token: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's actor token type <https://www.rfc-editor.org/rfc/rfc8693#section-3>
+    #[clap(long, env = "DOKEN_ACTOR_TOKEN_TYPE")]
+    pub actor_token_type: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's requested token type <https://www.rfc-editor.org/rfc/rfc8693#section-3>
+    #[clap(long, env = "DOKEN_REQUESTED_TOKEN_TYPE")]
+    pub requested_token_type: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's target resource <https://www.rfc-editor.org/rfc/rfc8693#section-2.1>
+    #[clap(long, env = "DOKEN_RESOURCE")]
+    pub resource: Option<String>,
+
+    /// PEM private key file (or a file with a shared secret for HS256) signing JWT Bearer Grant's assertion <https://www.rfc-editor.org/rfc/rfc7523#section-2.1>
+    #[clap(long, env = "DOKEN_ASSERTION_KEY")]
+    pub assertion_key: Option<String>,
+
+    /// JWT Bearer Grant's assertion signing algorithm
+    #[clap(long, value_enum, default_value_t = SigningAlgorithm::Rs256, env = "DOKEN_ASSERTION_ALGORITHM")]
+    pub assertion_algorithm: SigningAlgorithm,
+
+    /// JWT Bearer Grant's assertion `kid` header
+    #[clap(long, env = "DOKEN_ASSERTION_KEY_ID")]
+    pub assertion_key_id: Option<String>,
+
+    /// JWT Bearer Grant's assertion `iss` claim. Defaults to `--client-id` <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+    #[clap(long, env = "DOKEN_ASSERTION_ISSUER")]
+    pub assertion_issuer: Option<String>,
+
+    /// JWT Bearer Grant's assertion `sub` claim. Defaults to `--client-id` <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+    #[clap(long, env = "DOKEN_ASSERTION_SUBJECT")]
+    pub assertion_subject: Option<String>,
+
+    /// JWT Bearer Grant's assertion `aud` claim. Defaults to the token url <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+    #[clap(long, env = "DOKEN_ASSERTION_AUDIENCE")]
+    pub assertion_audience: Option<String>,
+
+    /// JWT Bearer Grant's assertion lifetime in seconds
+    #[clap(long, default_value_t = 300, env = "DOKEN_ASSERTION_LIFETIME")]
+    pub assertion_lifetime: u64,
+
+    /// JWT Bearer Grant's assertion additional claims as a JSON object
+    #[clap(long, env = "DOKEN_ASSERTION_CLAIMS")]
+    pub assertion_claims: Option<String>,
+
+    /// PEM private key file (or a file with a shared secret for HS256) signing authorization request objects of Authorization Code, Authorization Code with PKCE and Implicit Grants <https://www.rfc-editor.org/rfc/rfc9101>
+    #[clap(long, env = "DOKEN_REQUEST_OBJECT_KEY")]
+    pub request_object_key: Option<String>,
+
+    /// Authorization request object signing algorithm
+    #[clap(long, value_enum, default_value_t = SigningAlgorithm::Rs256, env = "DOKEN_REQUEST_OBJECT_ALGORITHM")]
+    pub request_object_algorithm: SigningAlgorithm,
+
+    /// Authorization request object `kid` header
+    #[clap(long, env = "DOKEN_REQUEST_OBJECT_KEY_ID")]
+    pub request_object_key_id: Option<String>,
+
+    /// Binds tokens to a DPoP key generated and kept in the state <https://www.rfc-editor.org/rfc/rfc9449>
+    #[clap(long, action, default_value_t = false, env = "DOKEN_DPOP")]
+    pub dpop: bool,
+
+    /// Token printed as an output
+    #[clap(long, value_enum, default_value_t = Output::AccessToken, env = "DOKEN_OUTPUT")]
+    pub output: Output,
+
     /// Authorization Code, Authorization Code with PKCE and Implicit Grants' timeout,
     #[clap(short, long, default_value_t = 30_000, env = "DOKEN_TIMEOUT")]
     pub timeout: u64,
 
+    /// Minimum remaining lifetime in seconds of a cached token. Tokens expiring sooner are refreshed
+    #[clap(long, default_value_t = 0, env = "DOKEN_MIN_TTL")]
+    pub min_ttl: u64,
+
+    /// Allowed clock difference in seconds between the machine and the IdP. Cached tokens are refreshed this much earlier
+    #[clap(long, default_value_t = DEFAULT_CLOCK_SKEW, env = "DOKEN_CLOCK_SKEW")]
+    pub clock_skew: u64,
+
+    /// Lifetime in seconds of tokens the IdP gives no expiry for, neither `expires_in` nor JWT `exp` claim. By default such tokens aren't reused
+    #[clap(long, default_value_t = 0, env = "DOKEN_DEFAULT_TOKEN_LIFETIME")]
+    pub default_token_lifetime: u64,
+
     /// When turned on ignores the state file and continues with a fresh flow
     #[clap(short, long, action, default_value_t = false)]
     pub force: bool,
@@ -88,6 +376,9 @@ pub struct Arguments {
     /// Profile defined in ~/.doken/config.toml file
     #[clap(long)]
     pub profile: Option<String>,
+
+    #[clap(subcommand)]
+    pub operation: Option<Operation>,
 }
 
 impl Default for Arguments {
@@ -96,20 +387,69 @@ impl Default for Arguments {
             grant: Grant::AuthorizationCodeWithPkce,
             token_url: Default::default(),
             authorization_url: Default::default(),
+            device_authorization_url: Default::default(),
+            pushed_authorization_request_url: Default::default(),
+            issuer: Default::default(),
+            jwks_url: Default::default(),
+            skip_id_token_validation: Default::default(),
+            userinfo_url: Default::default(),
+            revocation_url: Default::default(),
+            introspection_url: Default::default(),
+            par: Default::default(),
             discovery_url: Default::default(),
             callback_url: Default::default(),
+            system_browser: Default::default(),
+            no_browser: Default::default(),
+            no_browser_timeout: 300_000,
+            browser_profile_dir: Default::default(),
+            browser_executable: Default::default(),
+            browser_args: Default::default(),
+            browser_ws_url: Default::default(),
+            browser_debug_port: Default::default(),
             client_id: Default::default(),
             client_secret: Default::default(),
             client_secret_stdin: Default::default(),
+            client_auth_method: ClientAuthMethod::ClientSecretBasic,
+            client_assertion_key: Default::default(),
+            client_assertion_algorithm: SigningAlgorithm::Rs256,
+            client_assertion_key_id: Default::default(),
+            client_cert: Default::default(),
+            client_key: Default::default(),
+            client_cert_password: Default::default(),
             username: Default::default(),
             password: Default::default(),
             password_stdin: Default::default(),
             scope: Default::default(),
             audience: Default::default(),
+            subject_token: Default::default(),
+            subject_token_stdin: Default::default(),
+            subject_token_profile: Default::default(),
+            subject_token_type: ACCESS_TOKEN_TYPE.to_owned(),
+            actor_token: Default::default(),
+            actor_token_type: Default::default(),
+            requested_token_type: Default::default(),
+            resource: Default::default(),
+            assertion_key: Default::default(),
+            assertion_algorithm: SigningAlgorithm::Rs256,
+            assertion_key_id: Default::default(),
+            assertion_issuer: Default::default(),
+            assertion_subject: Default::default(),
+            assertion_audience: Default::default(),
+            assertion_lifetime: 300,
+            assertion_claims: Default::default(),
+            request_object_key: Default::default(),
+            request_object_algorithm: SigningAlgorithm::Rs256,
+            request_object_key_id: Default::default(),
+            dpop: Default::default(),
+            output: Output::AccessToken,
             timeout: 30_000,
+            min_ttl: Default::default(),
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            default_token_lifetime: Default::default(),
             force: Default::default(),
             debug: Default::default(),
             profile: Default::default(),
+            operation: Default::default(),
         }
     }
 }
@@ -141,6 +481,49 @@ impl Args {
         }
     }
 
+    fn requires_client_secret(args: &Arguments) -> bool {
+        !matches!(
+            args.client_auth_method,
+            ClientAuthMethod::PrivateKeyJwt
+                | ClientAuthMethod::TlsClientAuth
+                | ClientAuthMethod::SelfSignedTlsClientAuth
+                | ClientAuthMethod::None
+        )
+    }
+
+    fn assert_client_auth_method_arguments(args: &Arguments) {
+        let mut cmd: Command = Arguments::command();
+
+        match args.client_auth_method {
+            ClientAuthMethod::PrivateKeyJwt if args.client_assertion_key.is_none() => {
+                cmd.error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--client-assertion-key is required while used with `private_key_jwt` client authentication method.",
+                )
+                    .exit();
+            }
+            ClientAuthMethod::ClientSecretJwt
+                if args.client_secret.is_none() && !args.client_secret_stdin =>
+            {
+                cmd.error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--client-secret or --client-secret-stdin is required while used with `client_secret_jwt` client authentication method.",
+                )
+                    .exit();
+            }
+            ClientAuthMethod::TlsClientAuth | ClientAuthMethod::SelfSignedTlsClientAuth
+                if args.client_cert.is_none() =>
+            {
+                cmd.error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--client-cert is required while used with `tls_client_auth` or `self_signed_tls_client_auth` client authentication methods.",
+                )
+                    .exit();
+            }
+            _ => {}
+        }
+    }
+
     fn assert_grant_specific_arguments(args: &Arguments) {
         let mut cmd: Command = Arguments::command();
 
@@ -160,7 +543,10 @@ impl Args {
                     .exit();
                 }
 
-                if args.client_secret.is_none() && !args.client_secret_stdin {
+                if Self::requires_client_secret(args)
+                    && args.client_secret.is_none()
+                    && !args.client_secret_stdin
+                {
                     cmd.error(
                         ErrorKind::MissingRequiredArgument,
                         "--client-secret or --client-secret-stdin is required while used with `client-credentials` grant.",
@@ -193,7 +579,10 @@ impl Args {
                     .exit();
                 }
 
-                if args.client_secret.is_none() && !args.client_secret_stdin {
+                if Self::requires_client_secret(args)
+                    && args.client_secret.is_none()
+                    && !args.client_secret_stdin
+                {
                     cmd.error(
                         ErrorKind::MissingRequiredArgument,
                         "--client-secret or --client-secret-stdin is required while used with `client-credentials` grant.",
@@ -201,7 +590,77 @@ impl Args {
                         .exit();
                 }
             }
+            Grant::DeviceCode => {
+                if args.discovery_url.is_none()
+                    && (args.token_url.is_none() || args.device_authorization_url.is_none())
+                {
+                    cmd.error(
+                        ErrorKind::MissingRequiredArgument,
+                        "<--token-url, --device-authorization-url|--discovery-url> arguments have to be provided",
+                    )
+                    .exit();
+                }
+            }
+            Grant::TokenExchange => {
+                if args.token_url.is_none() && args.discovery_url.is_none() {
+                    cmd.error(
+                        ErrorKind::MissingRequiredArgument,
+                        "<--token-url|--discovery-url> arguments have to be provided",
+                    )
+                    .exit();
+                }
+
+                let subject_token_sources = [
+                    args.subject_token.is_some(),
+                    args.subject_token_stdin,
+                    args.subject_token_profile.is_some(),
+                ]
+                .iter()
+                .filter(|is_provided| **is_provided)
+                .count();
+
+                if subject_token_sources != 1 {
+                    cmd.error(
+                        ErrorKind::MissingRequiredArgument,
+                        "exactly one of --subject-token, --subject-token-stdin or --subject-token-profile is required while used with `token-exchange` grant.",
+                    )
+                        .exit();
+                }
+            }
+            Grant::JwtBearer => {
+                if args.token_url.is_none() && args.discovery_url.is_none() {
+                    cmd.error(
+                        ErrorKind::MissingRequiredArgument,
+                        "<--token-url|--discovery-url> arguments have to be provided",
+                    )
+                    .exit();
+                }
+
+                if args.assertion_key.is_none() {
+                    cmd.error(
+                        ErrorKind::MissingRequiredArgument,
+                        "--assertion-key is required while used with `jwt-bearer` grant.",
+                    )
+                    .exit();
+                }
+            }
             Grant::Implicit => {
+                if args.dpop {
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        "--dpop cannot be used with:\n\t--grant implicit",
+                    )
+                    .exit();
+                }
+
+                if args.no_browser {
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        "--no-browser cannot be used with:\n\t--grant implicit",
+                    )
+                    .exit();
+                }
+
                 if args.token_url.is_some() {
                     cmd.error(
                         ErrorKind::ArgumentConflict,
@@ -229,6 +688,18 @@ impl Args {
         }
     }
 
+    fn assert_operation_arguments(args: &Arguments) {
+        let mut cmd: Command = Arguments::command();
+
+        if matches!(args.operation, Some(Operation::DpopProof { .. })) && !args.dpop {
+            cmd.error(
+                ErrorKind::MissingRequiredArgument,
+                "--dpop is required while used with `dpop-proof` command.",
+            )
+            .exit();
+        }
+    }
+
     fn parse_client_secret(mut args: Arguments) -> Arguments {
         if args.client_secret.is_some() && std::env::var("DOKEN_CLIENT_SECRET").is_err() {
             eprintln!("Please use `--client-secret-stdin` as a more secure variant.");
@@ -253,7 +724,19 @@ impl Args {
         args
     }
 
+    fn parse_subject_token(mut args: Arguments) -> Arguments {
+        if args.subject_token_stdin {
+            let mut subject_token = String::new();
+            std::io::stdin().read_to_string(&mut subject_token).unwrap();
+            args.subject_token = Some(subject_token.trim().to_owned());
+        }
+
+        args
+    }
+
     async fn apply_profile() {
+        STARTUP_ENV.get_or_init(doken_env);
+
         let mut cmd: Command = Arguments::command();
         let args: Vec<String> = env::args().collect();
         let profile = match args.iter().position(|arg| arg.eq("--profile")) {
@@ -275,6 +758,27 @@ impl Args {
         }
     }
 
+    /// Arguments `doken --profile <profile>` would run with in the same environment, ex. to look up
+    /// the token cached by that run
+    pub async fn parse_profile(profile: &str) -> Result<Arguments> {
+        let current_env = doken_env();
+
+        // NOTE: The profile is applied on top of the startup environment, as the one of this run
+        // already contains its own profile
+        replace_doken_env(STARTUP_ENV.get().unwrap_or(&current_env));
+        let args = match ConfigFile::new()
+            .apply_profile(Some(profile.to_owned()))
+            .await
+        {
+            Ok(()) => Arguments::try_parse_from(["doken", "--profile", profile])
+                .with_context(|| format!("The profile `{profile}` is incomplete")),
+            Err(e) => Err(e),
+        };
+        replace_doken_env(&current_env);
+
+        args
+    }
+
     pub async fn parse() -> Arguments {
         log::debug!("Parsing application arguments...");
         if dotenv().is_ok() {
@@ -287,8 +791,11 @@ impl Args {
 
         let args = Arguments::parse();
         Self::assert_grant_specific_arguments(&args);
+        Self::assert_client_auth_method_arguments(&args);
+        Self::assert_operation_arguments(&args);
         let mut args = Self::parse_client_secret(args);
         args = Self::parse_password(args);
+        args = Self::parse_subject_token(args);
 
         log::debug!("Argument parsing done");
         log::debug!("Running with arguments: {args:#?}");
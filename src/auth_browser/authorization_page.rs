@@ -0,0 +1,25 @@
+use crate::token_info::TokenInfo;
+use anyhow::Result;
+use async_trait::async_trait;
+use oauth2::CsrfToken;
+use url::Url;
+
+/// Passes the user through the authorization endpoint and captures the redirect to `--callback-url`
+#[async_trait(?Send)]
+pub trait AuthorizationPage {
+    async fn get_code(
+        &self,
+        timeout: u64,
+        authorization_url: Url,
+        callback_url: Url,
+        csrf_token: CsrfToken,
+    ) -> Result<String>;
+
+    async fn get_token_data(
+        &self,
+        timeout: u64,
+        authorization_url: Url,
+        callback_url: Url,
+        csrf_token: CsrfToken,
+    ) -> Result<TokenInfo>;
+}
@@ -1,49 +1,170 @@
 use anyhow::{Result, anyhow};
 use chromiumoxide::browser::{Browser as CBrowser, BrowserConfig};
-use chromiumoxide::cdp::browser_protocol::target::CreateTargetParamsBuilder;
+use chromiumoxide::cdp::browser_protocol::fetch::EnableParams;
+use chromiumoxide::cdp::browser_protocol::target::{
+    CreateTargetParamsBuilder, EventTargetDestroyed,
+};
+use chromiumoxide::handler::HandlerConfig;
 use chromiumoxide::handler::viewport::Viewport;
 use chromiumoxide::{Handler, Page as CPage};
 use futures::StreamExt;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
-use tokio::sync::{OnceCell, oneshot};
+use tokio::sync::{OnceCell, watch};
 
 use super::page::Page;
+use crate::args::Arguments;
+
+/// Settings of the launched or attached browser. Without them a browser with a throwaway profile is launched
+#[derive(Default)]
+pub struct LaunchOptions {
+    /// Profile keeping cookies, so IdP sessions survive between runs
+    pub user_data_dir: Option<PathBuf>,
+
+    pub executable: Option<PathBuf>,
+
+    pub args: Vec<String>,
+
+    /// DevTools websocket url of a running browser used instead of launching one
+    pub ws_url: Option<String>,
+
+    /// Remote debugging port of a running browser used instead of launching one
+    pub debug_port: Option<u16>,
+}
+
+/// More: <https://chromedevtools.github.io/devtools-protocol/#endpoints>
+#[derive(Deserialize)]
+struct BrowserVersion {
+    #[serde(rename = "webSocketDebuggerUrl")]
+    web_socket_debugger_url: String,
+}
+
+async fn debugger_ws_url(debug_port: u16) -> Result<String> {
+    let version = reqwest::get(format!("http://127.0.0.1:{debug_port}/json/version"))
+        .await?
+        .error_for_status()?
+        .json::<BrowserVersion>()
+        .await?;
+
+    Ok(version.web_socket_debugger_url)
+}
+
+impl From<&Arguments> for LaunchOptions {
+    fn from(args: &Arguments) -> Self {
+        let user_data_dir = match &args.browser_profile_dir {
+            Some(browser_profile_dir) => PathBuf::from(browser_profile_dir),
+            None => match home::home_dir() {
+                Some(mut home_dir) => {
+                    home_dir.push(".doken/browser-profile");
+                    home_dir
+                }
+
+                None => panic!("Couldn't access $HOME_DIR"),
+            },
+        };
+
+        LaunchOptions {
+            user_data_dir: Some(user_data_dir),
+            executable: args.browser_executable.as_ref().map(PathBuf::from),
+            args: args.browser_args.to_owned(),
+            ws_url: args.browser_ws_url.to_owned(),
+            debug_port: args.browser_debug_port,
+        }
+    }
+}
 
 pub struct Browser {
     browser: OnceCell<CBrowser>,
     headless: bool,
+    options: LaunchOptions,
+    closed: watch::Sender<bool>,
+    attached: AtomicBool,
 }
 
 impl Browser {
     pub fn new(headless: bool) -> Self {
+        Self::with_options(headless, LaunchOptions::default())
+    }
+
+    pub fn with_options(headless: bool, options: LaunchOptions) -> Self {
         Browser {
             browser: OnceCell::new(),
             headless,
+            options,
+            closed: watch::Sender::new(false),
+            attached: AtomicBool::new(false),
         }
     }
 
     pub async fn open_page(&self) -> Result<Page> {
         let browser_page = self.lazy_open_page().await?;
-        let page = Page::new(browser_page);
+        let closed = self.watch_page_closed(&browser_page).await?;
+        // NOTE: Attached browser is the user's one, so only the tab opened by doken is closed
+        let page = Page::new(browser_page, closed, self.attached.load(Ordering::Relaxed));
         Ok(page)
     }
 
+    /// Turns to `true` once the user closes the page or the whole browser
+    async fn watch_page_closed(&self, page: &CPage) -> Result<watch::Receiver<bool>> {
+        let mut target_destroyed = self
+            .browser()
+            .await
+            .event_listener::<EventTargetDestroyed>()
+            .await?;
+        let mut browser_closed = self.closed.subscribe();
+        let target_id = page.target_id().to_owned();
+        let (page_closed, closed) = watch::channel(false);
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = async {
+                    while let Some(event) = target_destroyed.next().await {
+                        if event.target_id == target_id {
+                            break;
+                        }
+                    }
+                } => log::debug!("User closed the page"),
+                _ = browser_closed.wait_for(|closed| *closed) => {
+                    log::debug!("User closed the browser")
+                }
+            }
+
+            page_closed.send_replace(true);
+        });
+
+        Ok(closed)
+    }
+
     pub async fn browser(&self) -> &CBrowser {
         self.browser
             .get_or_init(|| async {
-                let (tx, _) = oneshot::channel::<()>();
+                let closed = self.closed.clone();
 
-                let (browser, mut handler) = Self::launch_browser(self.headless).await.unwrap();
+                let (browser, mut handler) = match self.connect_browser().await {
+                    Ok(Some(browser)) => {
+                        self.attached.store(true, Ordering::Relaxed);
+                        browser
+                    }
+                    Ok(None) => self.launch_browser().await.unwrap(),
+                    Err(e) => {
+                        log::debug!("Couldn't connect to the browser: {e}. Launching one...");
+                        self.launch_browser().await.unwrap()
+                    }
+                };
 
                 tokio::spawn(async move {
                     while let Some(h) = handler.next().await {
                         if h.is_err() {
                             log::error!("Browser failed: {}", h.err().unwrap());
-                            tx.send(()).unwrap();
                             log::error!("Handler created an error");
                             break;
                         }
                     }
+
+                    // NOTE: The handler stops once the connection to the browser is lost
+                    closed.send_replace(true);
                 });
                 browser
             })
@@ -56,6 +177,23 @@ impl Browser {
 
     async fn lazy_open_page(&self) -> Result<CPage> {
         let browser = self.browser().await;
+
+        if self.attached.load(Ordering::Relaxed) {
+            let page_config = CreateTargetParamsBuilder::default()
+                .url("about:blank")
+                .build()
+                .map_err(|e| anyhow!(e))?;
+            let page = browser
+                .new_page(page_config)
+                .await
+                .map_err(|e| anyhow!(e))?;
+
+            // NOTE: Requests are paused only in doken's tab. The user's other tabs keep working
+            page.execute(EnableParams::default()).await?;
+
+            return Ok(page);
+        }
+
         let page = self.wait_for_first_page(browser).await?;
 
         let create_new_page = || async {
@@ -107,7 +245,31 @@ impl Browser {
         }
     }
 
-    async fn launch_browser(headless: bool) -> Result<(CBrowser, Handler)> {
+    /// Connects to a browser started with `--remote-debugging-port`, if it's configured
+    async fn connect_browser(&self) -> Result<Option<(CBrowser, Handler)>> {
+        let ws_url = match (&self.options.ws_url, self.options.debug_port) {
+            (Some(ws_url), _) => ws_url.to_owned(),
+            (None, Some(debug_port)) => debugger_ws_url(debug_port).await?,
+            (None, None) => return Ok(None),
+        };
+
+        log::debug!("Connecting to the browser {ws_url}");
+        // NOTE: Same as the launched browser's, except the viewport of the user's windows is kept
+        // and requests aren't intercepted in every tab, but only in the one opened by doken
+        let config = HandlerConfig {
+            ignore_https_errors: false,
+            request_intercept: false,
+            cache_enabled: true,
+            ..HandlerConfig::default()
+        };
+        let browser = CBrowser::connect_with_config(ws_url, config)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        Ok(Some(browser))
+    }
+
+    async fn launch_browser(&self) -> Result<(CBrowser, Handler)> {
         log::debug!("Opening chromium instance");
         const WIDTH: u32 = 800;
         const HEIGHT: u32 = 1000;
@@ -119,16 +281,26 @@ impl Browser {
 
         let mut config = BrowserConfig::builder();
 
-        if !headless {
+        if !self.headless {
             config = config.with_head();
         }
 
+        if let Some(user_data_dir) = &self.options.user_data_dir {
+            log::debug!("Using browser profile {}", user_data_dir.display());
+            config = config.user_data_dir(user_data_dir);
+        }
+
+        if let Some(executable) = &self.options.executable {
+            config = config.chrome_executable(executable);
+        }
+
         config = config
             .viewport(viewport)
             .window_size(WIDTH, HEIGHT)
             .enable_request_intercept()
             .respect_https_errors()
-            .enable_cache();
+            .enable_cache()
+            .args(&self.options.args);
 
         CBrowser::launch(config.build().map_err(|e| anyhow!(e))?)
             .await
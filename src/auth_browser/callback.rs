@@ -0,0 +1,168 @@
+use crate::token_info::TokenInfo;
+use oauth2::CsrfToken;
+use std::borrow::Cow;
+use std::ops::Add;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum RequestError {
+    #[error("No requests with required data. Timeout.")]
+    Timeout,
+
+    #[error("The user closed the browser")]
+    BrowserClosed,
+}
+
+pub const CONTENT_OK: &str = "<html><head></head><body><h1>OK</h1></body></html>";
+pub const CONTENT_NOT_OK: &str = "<html><head></head><body><h1>NOT OK</h1></body></html>";
+
+/// Whether the request was sent to `--callback-url`
+pub fn is_callback(request_url: &Url, callback_url: &Url) -> bool {
+    request_url.origin() == callback_url.origin() && request_url.path() == callback_url.path()
+}
+
+/// Authorization code passed in the query of the redirect. More: <https://www.rfc-editor.org/rfc/rfc6749#section-4.1.2>
+pub fn code_from_url(request_url: &Url, csrf_token: &CsrfToken) -> Option<String> {
+    let state = request_url.query_pairs().find(|qp| qp.0.eq("state"));
+    let code = request_url.query_pairs().find(|qp| qp.0.eq("code"));
+
+    match (state, code) {
+        (Some((_, state)), Some((_, code))) => {
+            if state == *csrf_token.secret() {
+                let code = code.to_string();
+                log::debug!("Given code: {code}");
+
+                Some(code)
+            } else {
+                log::debug!("Incorrect CSRF token. Ignoring...");
+
+                None
+            }
+        }
+        _ => {
+            log::debug!("Call to server without a state and/or a code parameter. Ignoring...");
+
+            None
+        }
+    }
+}
+
+/// Token posted to the redirect. More: <https://openid.net/specs/oauth-v2-form-post-response-mode-1_0.html>
+pub fn token_data_from_form(body: &[u8], csrf_token: &CsrfToken) -> Option<TokenInfo> {
+    let form_params = form_urlencoded::parse(body).collect::<Vec<(Cow<str>, Cow<str>)>>();
+
+    let Some((_, access_token)) = form_params
+        .iter()
+        .find(|(name, _value)| name == "access_token")
+    else {
+        log::debug!("Cannot find access_token in the HTTP Post request. Ignoring...");
+
+        return None;
+    };
+
+    let Some((_, expires_in)) = form_params
+        .iter()
+        .find(|(name, _value)| name == "expires_in")
+    else {
+        log::debug!("Cannot find expires_in in the HTTP Post request. Ignoring...");
+
+        return None;
+    };
+
+    let Some((_, state)) = form_params.iter().find(|(name, _value)| name == "state") else {
+        log::debug!("Cannot find state in the HTTP Post request. Ignoring...");
+
+        return None;
+    };
+
+    let Ok(expires_in) = expires_in.parse::<u64>() else {
+        log::debug!("expires_in is an incorrect number. Ignoring...");
+
+        return None;
+    };
+
+    let token_type = form_params
+        .iter()
+        .find(|(name, _value)| name == "token_type")
+        .map(|(_, token_type)| token_type.to_string());
+
+    if state == csrf_token.secret() {
+        Some(TokenInfo {
+            access_token: access_token.to_string(),
+            refresh_token: None,
+            id_token: None,
+            expires: Some(SystemTime::now().add(Duration::from_secs(expires_in))),
+            scope: None,
+            token_type,
+            cert_thumbprint: None,
+        })
+    } else {
+        log::debug!("Incorrect CSRF token. Aborting...");
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use super::*;
+
+    fn get_csrf_token() -> CsrfToken {
+        CsrfToken::new("test-state".to_owned())
+    }
+
+    #[test]
+    fn it_reads_code_with_matching_state() {
+        let url = Url::parse("http://127.0.0.1/callback?code=test-code&state=test-state").unwrap();
+
+        assert_eq!(
+            code_from_url(&url, &get_csrf_token()),
+            Some("test-code".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_ignores_code_with_other_state() {
+        let other_state =
+            Url::parse("http://127.0.0.1/callback?code=test-code&state=other-state").unwrap();
+        let without_state = Url::parse("http://127.0.0.1/callback?code=test-code").unwrap();
+        let without_code = Url::parse("http://127.0.0.1/callback?state=test-state").unwrap();
+
+        assert_eq!(code_from_url(&other_state, &get_csrf_token()), None);
+        assert_eq!(code_from_url(&without_state, &get_csrf_token()), None);
+        assert_eq!(code_from_url(&without_code, &get_csrf_token()), None);
+    }
+
+    #[test]
+    fn it_reads_posted_token_with_matching_state() {
+        let body =
+            b"access_token=test-access-token&token_type=Bearer&expires_in=300&state=test-state";
+
+        let token_info = token_data_from_form(body, &get_csrf_token()).unwrap();
+
+        assert_eq!(token_info.access_token, "test-access-token");
+        assert_eq!(token_info.token_type, Some("Bearer".to_owned()));
+        assert!(token_info.expires.unwrap() > SystemTime::now() + Duration::from_secs(290));
+    }
+
+    #[test]
+    fn it_ignores_posted_token_with_other_state_or_missing_values() {
+        let other_state = b"access_token=test-access-token&expires_in=300&state=other-state";
+        let without_state = b"access_token=test-access-token&expires_in=300";
+        let without_expiry = b"access_token=test-access-token&state=test-state";
+        let incorrect_expiry = b"access_token=test-access-token&expires_in=soon&state=test-state";
+
+        for body in [
+            &other_state[..],
+            &without_state[..],
+            &without_expiry[..],
+            &incorrect_expiry[..],
+        ] {
+            assert!(token_data_from_form(body, &get_csrf_token()).is_none());
+        }
+    }
+}
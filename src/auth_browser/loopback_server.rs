@@ -0,0 +1,368 @@
+use super::authorization_page::AuthorizationPage;
+use super::callback::{
+    CONTENT_NOT_OK, CONTENT_OK, RequestError, code_from_url, is_callback, token_data_from_form,
+};
+use crate::token_info::TokenInfo;
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use oauth2::CsrfToken;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use url::{Host, Url};
+
+/// Time a single connection has to send its request, before it's dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Callbacks carry a few short parameters, so anything bigger is rejected before reading it
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Request sent by the browser to the loopback interface
+struct CallbackRequest {
+    method: String,
+    url: Url,
+    body: Vec<u8>,
+}
+
+/// Redirect server of native apps, that uses the system browser instead of Chromium.
+/// More: <https://www.rfc-editor.org/rfc/rfc8252#section-7.3>
+pub struct LoopbackServer {
+    listener: TcpListener,
+    callback_url: Url,
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        Some(Host::Domain(domain)) => domain == "localhost",
+        None => false,
+    }
+}
+
+/// Opens the url in the default browser of the OS
+fn open_system_browser(url: &Url) -> Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        // NOTE: `start` treats the first quoted argument as the window title
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command
+        .arg(url.as_str())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to open the system browser")?;
+
+    Ok(())
+}
+
+async fn read_request(stream: &mut TcpStream, callback_url: &Url) -> Result<CallbackRequest> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let [method, target, _] = request_line.split_whitespace().collect::<Vec<_>>()[..] else {
+        bail!("Malformed request line: {request_line}");
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).await?;
+
+        if header.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse()?;
+        }
+    }
+
+    if content_length as u64 > MAX_REQUEST_SIZE {
+        bail!("Request body of {content_length} bytes is too large");
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(CallbackRequest {
+        method: method.to_owned(),
+        url: callback_url.join(target)?,
+        body,
+    })
+}
+
+async fn write_response(stream: &mut TcpStream, content: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{content}",
+        content.len()
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+impl LoopbackServer {
+    /// Listens on the port of `--callback-url` or on an ephemeral one, when the url has no port
+    pub async fn bind(callback_url: &str) -> Result<LoopbackServer> {
+        let mut callback_url =
+            Url::parse(callback_url).context("`--callback-url` is not a valid url")?;
+
+        if callback_url.scheme() != "http" || !is_loopback(&callback_url) {
+            bail!(
+                "`--system-browser` requires `--callback-url` on the loopback interface ex. http://127.0.0.1/callback"
+            );
+        }
+
+        let host = callback_url.host_str().unwrap_or_default();
+        let listener = TcpListener::bind(format!("{host}:{}", callback_url.port().unwrap_or(0)))
+            .await
+            .context("Couldn't listen on `--callback-url`")?;
+
+        let port = listener.local_addr()?.port();
+        callback_url
+            .set_port(Some(port))
+            .map_err(|_| anyhow!("Couldn't set port of `--callback-url`"))?;
+        log::debug!("Listening on {callback_url}");
+
+        Ok(LoopbackServer {
+            listener,
+            callback_url,
+        })
+    }
+
+    /// `--callback-url` with the port the server listens on
+    pub fn callback_url(&self) -> &Url {
+        &self.callback_url
+    }
+
+    async fn accept_callback<TResponse, F>(&self, f: F) -> Result<TResponse>
+    where
+        F: Fn(&CallbackRequest) -> Option<TResponse>,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<(TcpStream, CallbackRequest)>();
+
+        loop {
+            let (mut stream, request) = tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (mut stream, _) = accepted?;
+                    let sender = sender.clone();
+                    let callback_url = self.callback_url.to_owned();
+
+                    // NOTE: Every connection is read in its own task, so idle ones, ex. opened
+                    // ahead by the browser, don't block the callback
+                    tokio::spawn(async move {
+                        match timeout(READ_TIMEOUT, read_request(&mut stream, &callback_url)).await {
+                            Ok(Ok(request)) => {
+                                let _ = sender.send((stream, request));
+                            }
+                            Ok(Err(e)) => log::debug!("Failed to read request: {e}"),
+                            Err(_) => log::debug!("Request wasn't sent in time. Ignoring..."),
+                        }
+                    });
+
+                    continue;
+                }
+                Some(received) = receiver.recv() => received,
+            };
+
+            let response = if is_callback(&request.url, &self.callback_url) {
+                log::debug!("Received request to `--callback-url` {}", self.callback_url);
+                f(&request)
+            } else {
+                None
+            };
+
+            if let Err(e) = write_response(
+                &mut stream,
+                if response.is_some() {
+                    CONTENT_OK
+                } else {
+                    CONTENT_NOT_OK
+                },
+            )
+            .await
+            {
+                log::error!("Failed to respond: {e}");
+            }
+
+            if let Some(response) = response {
+                return Ok(response);
+            }
+        }
+    }
+
+    async fn process_request<TResponse, F>(
+        &self,
+        timeout: u64,
+        authorization_url: Url,
+        f: F,
+    ) -> Result<TResponse>
+    where
+        F: Fn(&CallbackRequest) -> Option<TResponse>,
+    {
+        eprintln!("Please open {authorization_url} to authorize");
+        if let Err(e) = open_system_browser(&authorization_url) {
+            log::debug!("{e}");
+        }
+
+        tokio::select! {
+            _ = sleep(Duration::from_millis(timeout)) => {
+                log::debug!("Timeout");
+                Err(RequestError::Timeout.into())
+            }
+            response = self.accept_callback(f) => response
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl AuthorizationPage for LoopbackServer {
+    async fn get_code(
+        &self,
+        timeout: u64,
+        authorization_url: Url,
+        _callback_url: Url,
+        csrf_token: CsrfToken,
+    ) -> Result<String> {
+        self.process_request(timeout, authorization_url, |request| {
+            code_from_url(&request.url, &csrf_token)
+        })
+        .await
+    }
+
+    async fn get_token_data(
+        &self,
+        timeout: u64,
+        authorization_url: Url,
+        _callback_url: Url,
+        csrf_token: CsrfToken,
+    ) -> Result<TokenInfo> {
+        self.process_request(timeout, authorization_url, |request| {
+            match request.method.as_str() {
+                "POST" => token_data_from_form(&request.body, &csrf_token),
+                _ => {
+                    log::debug!(
+                        "Call to server without a state and/or a code parameter. Ignoring..."
+                    );
+
+                    None
+                }
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use super::*;
+
+    async fn send_request(request: &'static str) -> Result<CallbackRequest> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let callback_url = Url::parse(&format!("http://{address}/callback"))?;
+
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(address).await?;
+            stream.write_all(request.as_bytes()).await
+        });
+
+        let (mut stream, _) = listener.accept().await?;
+        let request = read_request(&mut stream, &callback_url).await;
+        client.await??;
+
+        request
+    }
+
+    #[test]
+    fn it_accepts_loopback_callback_urls() {
+        let is_loopback_url = |url: &str| is_loopback(&Url::parse(url).unwrap());
+
+        assert!(is_loopback_url("http://127.0.0.1/callback"));
+        assert!(is_loopback_url("http://127.0.0.2:8080/callback"));
+        assert!(is_loopback_url("http://[::1]:8080/callback"));
+        assert!(is_loopback_url("http://localhost/callback"));
+        assert!(!is_loopback_url("http://192.168.0.1/callback"));
+        assert!(!is_loopback_url("http://my-app.com/callback"));
+        assert!(!is_loopback_url("http://localhost.my-app.com/callback"));
+    }
+
+    #[tokio::test]
+    async fn it_reads_posted_callback() {
+        let request = send_request(
+            "POST /callback HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 11\r\n\r\nstate=x&a=b",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url.path(), "/callback");
+        assert_eq!(request.body, b"state=x&a=b");
+    }
+
+    #[tokio::test]
+    async fn it_accepts_callback_while_other_connection_is_idle() {
+        let server = LoopbackServer::bind("http://127.0.0.1/callback")
+            .await
+            .unwrap();
+        let address = server.listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let idle = TcpStream::connect(address).await?;
+            let mut callback = TcpStream::connect(address).await?;
+            callback
+                .write_all(b"GET /callback?code=test-code HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+                .await?;
+
+            let mut response = String::new();
+            callback.read_to_string(&mut response).await?;
+            drop(idle);
+
+            Ok::<_, std::io::Error>(response)
+        });
+
+        let code = timeout(
+            READ_TIMEOUT / 2,
+            server.accept_callback(|request| {
+                request
+                    .url
+                    .query_pairs()
+                    .find(|(name, _)| name == "code")
+                    .map(|(_, code)| code.into_owned())
+            }),
+        )
+        .await
+        .expect("The idle connection blocked the callback")
+        .unwrap();
+        let response = client.await.unwrap().unwrap();
+
+        assert_eq!(code, "test-code");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn it_rejects_too_large_body() {
+        let request =
+            send_request("POST /callback HTTP/1.1\r\nContent-Length: 1000000000\r\n\r\nstate=x")
+                .await;
+
+        assert!(request.is_err());
+    }
+}
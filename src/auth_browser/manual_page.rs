@@ -0,0 +1,181 @@
+use super::authorization_page::AuthorizationPage;
+use super::callback::{RequestError, code_from_url};
+use crate::token_info::TokenInfo;
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use oauth2::CsrfToken;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::sleep;
+use url::Url;
+
+// NOTE: Standard input may be already taken by `--client-secret-stdin` or `--password-stdin`
+const TTY_PATH: &str = if cfg!(windows) { "CONIN$" } else { "/dev/tty" };
+
+/// Out-of-band authorization. The user opens the url on any device and pastes back the redirect
+pub struct ManualPage {
+    pkce: bool,
+    /// `--no-browser-timeout`, used instead of `--timeout`
+    timeout: u64,
+}
+
+fn read_line() -> Result<String> {
+    let tty = File::open(TTY_PATH).context("Couldn't open the terminal to read the redirect")?;
+
+    let mut line = String::new();
+    if BufReader::new(tty).read_line(&mut line)? == 0 {
+        bail!("The terminal was closed before the redirect was pasted");
+    }
+
+    Ok(line.trim().to_owned())
+}
+
+/// Reads a line on a detached thread. A blocking task would keep the runtime from shutting down
+/// after the timeout until the user presses enter
+async fn read_tty_line() -> Result<String> {
+    let (sender, receiver) = oneshot::channel();
+
+    thread::spawn(move || sender.send(read_line()));
+
+    receiver
+        .await
+        .context("Couldn't read the redirect from the terminal")?
+}
+
+/// Reads the code from the pasted redirect url. Bare code is accepted only with PKCE, as it has no
+/// state to check and the code verifier ties it to this authorization request
+fn parse_code(input: &str, csrf_token: &CsrfToken, pkce: bool) -> Result<String> {
+    let redirect_url = match Url::parse(input) {
+        Ok(redirect_url) => redirect_url,
+        Err(_) if pkce => return Ok(input.to_owned()),
+        Err(_) => bail!("It's not a url. The code can't be pasted alone without PKCE"),
+    };
+
+    // NOTE: Error response of the authorization server. More: <https://www.rfc-editor.org/rfc/rfc6749#section-4.1.2.1>
+    if let Some((_, error)) = redirect_url.query_pairs().find(|(name, _)| name == "error") {
+        let description = redirect_url
+            .query_pairs()
+            .find(|(name, _)| name == "error_description")
+            .map(|(_, description)| format!(": {description}"))
+            .unwrap_or_default();
+
+        bail!("Authorization failed with `{error}`{description}");
+    }
+
+    code_from_url(&redirect_url, csrf_token)
+        .context("The url doesn't contain a code with the state of this authorization request")
+}
+
+impl ManualPage {
+    pub fn new(pkce: bool, timeout: u64) -> ManualPage {
+        ManualPage { pkce, timeout }
+    }
+
+    async fn read_code(&self, csrf_token: &CsrfToken) -> Result<String> {
+        loop {
+            match self.pkce {
+                true => eprint!("Paste the url you were redirected to (or the code): "),
+                false => eprint!("Paste the url you were redirected to: "),
+            }
+
+            let input = read_tty_line().await?;
+
+            if input.is_empty() {
+                continue;
+            }
+
+            match parse_code(&input, csrf_token, self.pkce) {
+                Ok(code) => return Ok(code),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl AuthorizationPage for ManualPage {
+    async fn get_code(
+        &self,
+        _timeout: u64,
+        authorization_url: Url,
+        _callback_url: Url,
+        csrf_token: CsrfToken,
+    ) -> Result<String> {
+        eprintln!("Please open {authorization_url} to authorize");
+
+        tokio::select! {
+            _ = sleep(Duration::from_millis(self.timeout)) => {
+                log::debug!("Timeout");
+                Err(RequestError::Timeout.into())
+            }
+            code = self.read_code(&csrf_token) => code
+        }
+    }
+
+    async fn get_token_data(
+        &self,
+        _timeout: u64,
+        _authorization_url: Url,
+        _callback_url: Url,
+        _csrf_token: CsrfToken,
+    ) -> Result<TokenInfo> {
+        bail!("Tokens of Implicit grant are posted to `--callback-url`, so they can't be pasted")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use super::*;
+
+    fn csrf_token() -> CsrfToken {
+        CsrfToken::new("state".to_owned())
+    }
+
+    #[test]
+    fn it_reads_the_code_from_the_redirect_url() {
+        let code = parse_code(
+            "http://localhost:8080/callback?code=abc&state=state",
+            &csrf_token(),
+            false,
+        );
+
+        assert_eq!(code.unwrap(), "abc");
+    }
+
+    #[test]
+    fn it_accepts_the_bare_code_only_with_pkce() {
+        assert_eq!(parse_code("abc", &csrf_token(), true).unwrap(), "abc");
+        assert!(parse_code("abc", &csrf_token(), false).is_err());
+    }
+
+    #[test]
+    fn it_rejects_the_redirect_with_another_state() {
+        let code = parse_code(
+            "http://localhost:8080/callback?code=abc&state=other",
+            &csrf_token(),
+            true,
+        );
+
+        assert!(code.is_err());
+    }
+
+    #[test]
+    fn it_reports_the_error_response() {
+        let error = parse_code(
+            "http://localhost:8080/callback?error=access_denied&error_description=Denied&state=state",
+            &csrf_token(),
+            true,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Authorization failed with `access_denied`: Denied"
+        );
+    }
+}
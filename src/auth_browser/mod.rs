@@ -1,2 +1,6 @@
+pub mod authorization_page;
 pub mod browser;
+pub mod callback;
+pub mod loopback_server;
+pub mod manual_page;
 pub mod page;
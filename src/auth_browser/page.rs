@@ -1,5 +1,10 @@
+use super::authorization_page::AuthorizationPage;
+use super::callback::{
+    CONTENT_NOT_OK, CONTENT_OK, RequestError, code_from_url, is_callback, token_data_from_form,
+};
 use crate::token_info::TokenInfo;
 use anyhow::{Result, anyhow};
+use async_trait::async_trait;
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
 use chromiumoxide::Page as CPage;
@@ -8,35 +13,25 @@ use chromiumoxide::cdp::browser_protocol::fetch::{
 };
 use futures::StreamExt;
 use oauth2::CsrfToken;
-use std::borrow::Cow;
-use std::ops::Add;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use thiserror::Error;
-use tokio::sync::oneshot;
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
 use tokio::time::sleep;
 use url::Url;
 
-#[derive(Error, Debug)]
-enum RequestError {
-    #[error("No requests with required data. Timeout.")]
-    Timeout,
-
-    // TODO: Implement channels to all pages to close them
-    #[error("The user closed the browser")]
-    _BrowserClosed,
-}
-
-const CONTENT_OK: &str = "<html><head></head><body><h1>OK</h1></body></html>";
-const CONTENT_NOT_OK: &str = "<html><head></head><body><h1>NOT OK</h1></body></html>";
-
 pub struct Page {
     page: CPage,
+    closed: watch::Receiver<bool>,
+    close_when_done: bool,
 }
 
 impl Page {
-    pub fn new(page: CPage) -> Self {
-        Page { page }
+    pub fn new(page: CPage, closed: watch::Receiver<bool>, close_when_done: bool) -> Self {
+        Page {
+            page,
+            closed,
+            close_when_done,
+        }
     }
 
     async fn process_request<TResponse, F>(
@@ -57,9 +52,7 @@ impl Page {
         tokio::spawn(async move {
             while let Some(event) = request_paused.next().await {
                 let request_url = Url::parse(&event.request.url).unwrap();
-                if request_url.origin() == callback_url.origin()
-                    && request_url.path() == callback_url.path()
-                {
+                if is_callback(&request_url, &callback_url) {
                     log::debug!("Received request to `--callback-url` {callback_url}");
 
                     let response = f(event.clone());
@@ -98,6 +91,8 @@ impl Page {
         log::debug!("Opening authorization page {authorization_url}");
         self.page.goto(authorization_url.as_str()).await?;
 
+        let mut closed = self.closed.clone();
+
         let response = tokio::select! {
             _ = sleep(Duration::from_millis(timeout)) => {
                 log::debug!("Timeout");
@@ -106,16 +101,30 @@ impl Page {
             Ok(response) = rx_browser => {
                 Ok::<TResponse, anyhow::Error>(response)
             }
-            // _ = &mut self.rx_handle => {
-            //     log::debug!("User closed the browser");
-            //     Err::<TResponse, anyhow::Error>(RequestError::BrowserClosed.into())
-            // }
+            _ = closed.wait_for(|closed| *closed) => {
+                log::debug!("User closed the browser");
+                Err::<TResponse, anyhow::Error>(RequestError::BrowserClosed.into())
+            }
         };
 
+        if self.close_when_done
+            && let Err(e) = self.page.clone().close().await
+        {
+            log::debug!("Failed to close the page: {e}");
+        }
+
         response
     }
 
-    pub async fn get_code(
+    pub async fn close(self) -> Result<()> {
+        self.page.close().await.map_err(|e| anyhow!(e))?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl AuthorizationPage for Page {
+    async fn get_code(
         &self,
         timeout: u64,
         authorization_url: Url,
@@ -123,36 +132,12 @@ impl Page {
         csrf_token: CsrfToken,
     ) -> Result<String> {
         self.process_request(timeout, authorization_url, callback_url, move |event| {
-            let request_url = Url::parse(&event.request.url).unwrap();
-            let state = request_url.query_pairs().find(|qp| qp.0.eq("state"));
-            let code = request_url.query_pairs().find(|qp| qp.0.eq("code"));
-
-            match (state, code) {
-                (Some((_, state)), Some((_, code))) => {
-                    if state == *csrf_token.secret() {
-                        let code = code.to_string();
-                        log::debug!("Given code: {code}");
-
-                        Some(code)
-                    } else {
-                        log::debug!("Incorrect CSRF token. Ignoring...");
-
-                        None
-                    }
-                }
-                _ => {
-                    log::debug!(
-                        "Call to server without a state and/or a code parameter. Ignoring..."
-                    );
-
-                    None
-                }
-            }
+            code_from_url(&Url::parse(&event.request.url).unwrap(), &csrf_token)
         })
         .await
     }
 
-    pub async fn get_token_data(
+    async fn get_token_data(
         &self,
         timeout: u64,
         authorization_url: Url,
@@ -177,43 +162,7 @@ impl Page {
                         .collect::<Vec<Vec<u8>>>()
                         .join("&".as_bytes());
 
-                    let form_params =
-                        form_urlencoded::parse(body.as_slice())
-                            .collect::<Vec<(Cow<str>, Cow<str>)>>();
-
-                    let (_, access_token) = form_params
-                        .iter()
-                        .find(|(name, _value)| name == "access_token")
-                        .expect("Cannot find access_token in the HTTP Post request.");
-
-                    let (_, expires_in) = form_params
-                        .iter()
-                        .find(|(name, _value)| name == "expires_in")
-                        .expect("Cannot find expires_in in the HTTP Post request.");
-
-                    let (_, state) = form_params
-                        .iter()
-                        .find(|(name, _value)| name == "state")
-                        .expect("Cannot find state in the HTTP Post request.");
-
-                    if state == csrf_token.secret() {
-                        Some(TokenInfo {
-                            access_token: access_token.to_string(),
-                            refresh_token: None,
-                            expires: Some(
-                                SystemTime::now().add(Duration::from_secs(
-                                    expires_in
-                                        .parse::<u64>()
-                                        .expect("expires_in is an incorrect number"),
-                                )),
-                            ),
-                            scope: None,
-                        })
-                    } else {
-                        log::debug!("Incorrect CSRF token. Aborting...");
-
-                        None
-                    }
+                    token_data_from_form(&body, &csrf_token)
                 }
                 _ => {
                     log::debug!(
@@ -226,9 +175,4 @@ impl Page {
         )
         .await
     }
-
-    pub async fn close(self) -> Result<()> {
-        self.page.close().await.map_err(|e| anyhow!(e))?;
-        Ok(())
-    }
 }
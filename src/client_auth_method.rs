@@ -0,0 +1,35 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum ClientAuthMethod {
+    /// Client Secret sent using HTTP Basic authentication. More: <https://www.rfc-editor.org/rfc/rfc6749#section-2.3.1>
+    ClientSecretBasic,
+    /// Client Secret sent in the request body. More: <https://www.rfc-editor.org/rfc/rfc6749#section-2.3.1>
+    ClientSecretPost,
+    /// JWT signed with the Client Secret using HMAC. More: <https://openid.net/specs/openid-connect-core-1_0.html#ClientAuthentication>
+    ClientSecretJwt,
+    /// JWT signed with a private key. More: <https://openid.net/specs/openid-connect-core-1_0.html#ClientAuthentication>
+    PrivateKeyJwt,
+    /// Mutual-TLS with a PKI certificate. More: <https://www.rfc-editor.org/rfc/rfc8705#section-2.1>
+    TlsClientAuth,
+    /// Mutual-TLS with a self-signed certificate. More: <https://www.rfc-editor.org/rfc/rfc8705#section-2.2>
+    SelfSignedTlsClientAuth,
+    /// No client authentication. Only the Client Identifier is sent
+    None,
+}
+
+impl ClientAuthMethod {
+    /// Method the client authenticates with. Secret based methods send the Client Identifier
+    /// only, when no secret is provided, ex. for public clients using PKCE
+    pub fn effective(&self, client_secret: Option<&str>) -> ClientAuthMethod {
+        match (self, client_secret) {
+            (ClientAuthMethod::ClientSecretBasic | ClientAuthMethod::ClientSecretPost, None) => {
+                ClientAuthMethod::None
+            }
+            _ => self.to_owned(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::args::Arguments;
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use p12_keystore::KeyStore;
+use pem::Pem;
+use reqwest::Identity;
+use sha2::{Digest, Sha256};
+use std::fs;
+
+/// Client certificate used for Mutual-TLS. More: <https://www.rfc-editor.org/rfc/rfc8705>
+pub struct ClientCertificate {
+    identity_pem: Vec<u8>,
+    thumbprint: String,
+}
+
+impl ClientCertificate {
+    fn read(path: &str) -> Result<Vec<u8>> {
+        fs::read(path).with_context(|| format!("Cannot read the certificate file {path}"))
+    }
+
+    /// Converts PKCS#12 archive to PEM, because `reqwest` with `rustls` accepts PEM only
+    fn pkcs12_to_pem(pkcs12: &[u8], password: &str) -> Result<Vec<Pem>> {
+        let key_store = KeyStore::from_pkcs12(pkcs12, password)
+            .map_err(|e| anyhow!(e))
+            .context("Cannot decrypt PKCS#12 client certificate")?;
+
+        let (_, chain) = key_store
+            .private_key_chain()
+            .context("PKCS#12 client certificate doesn't contain a private key")?;
+
+        let mut pems: Vec<Pem> = chain
+            .chain()
+            .iter()
+            .map(|cert| Pem::new("CERTIFICATE", cert.as_der()))
+            .collect();
+        pems.push(Pem::new("PRIVATE KEY", chain.key()));
+
+        Ok(pems)
+    }
+
+    pub fn from_args(args: &Arguments) -> Result<Option<ClientCertificate>> {
+        let cert_path = match &args.client_cert {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let cert = Self::read(cert_path)?;
+
+        let pems = match (&args.client_key, cert.starts_with(b"-----BEGIN")) {
+            (Some(key_path), _) => {
+                let mut pems = pem::parse_many(&cert)?;
+                pems.extend(pem::parse_many(Self::read(key_path)?)?);
+                pems
+            }
+            (None, true) => pem::parse_many(&cert)?,
+            (None, false) => Self::pkcs12_to_pem(
+                &cert,
+                args.client_cert_password.as_deref().unwrap_or_default(),
+            )?,
+        };
+
+        // NOTE: The first certificate is the leaf one
+        let leaf = pems
+            .iter()
+            .find(|pem| pem.tag() == "CERTIFICATE")
+            .with_context(|| format!("No certificate found in {cert_path}"))?;
+
+        let thumbprint = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(leaf.contents()));
+
+        Ok(Some(ClientCertificate {
+            identity_pem: pem::encode_many(&pems).into_bytes(),
+            thumbprint,
+        }))
+    }
+
+    pub fn identity(&self) -> Result<Identity> {
+        Identity::from_pem(&self.identity_pem).context("Incorrect client certificate or key")
+    }
+
+    /// `x5t#S256` thumbprint of the certificate. More: <https://www.rfc-editor.org/rfc/rfc8705#section-3.1>
+    pub fn thumbprint(&self) -> &str {
+        &self.thumbprint
+    }
+}
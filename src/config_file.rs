@@ -5,7 +5,10 @@ use tokio::fs;
 use serde::{Deserialize, Serialize};
 use serde_variant::to_variant_name;
 
+use crate::client_auth_method::ClientAuthMethod;
 use crate::grant::Grant;
+use crate::output::Output;
+use crate::signing_algorithm::SigningAlgorithm;
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Profile {
@@ -18,18 +21,90 @@ pub struct Profile {
     /// OAuth 2.0 authorization initiation url
     pub authorization_url: Option<String>,
 
+    /// OAuth 2.0 device authorization url <https://www.rfc-editor.org/rfc/rfc8628#section-3.1>
+    pub device_authorization_url: Option<String>,
+
+    /// OAuth 2.0 pushed authorization request url <https://www.rfc-editor.org/rfc/rfc9126#section-2>
+    pub pushed_authorization_request_url: Option<String>,
+
+    /// OpenID Connect issuer identifier checked in ID tokens <https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation>
+    pub issuer: Option<String>,
+
+    /// JSON Web Key Set url of keys signing ID tokens <https://www.rfc-editor.org/rfc/rfc7517#section-5>
+    pub jwks_url: Option<String>,
+
+    /// Accepts ID tokens without validating them
+    pub skip_id_token_validation: Option<bool>,
+
+    /// OpenID Connect UserInfo url <https://openid.net/specs/openid-connect-core-1_0.html#UserInfo>
+    pub userinfo_url: Option<String>,
+
+    /// OAuth 2.0 token revocation url <https://www.rfc-editor.org/rfc/rfc7009#section-2>
+    pub revocation_url: Option<String>,
+
+    /// OAuth 2.0 token introspection url <https://www.rfc-editor.org/rfc/rfc7662#section-2>
+    pub introspection_url: Option<String>,
+
+    /// Pushes parameters of Authorization Code grants before opening a browser <https://www.rfc-editor.org/rfc/rfc9126>
+    pub par: Option<bool>,
+
     /// OpenID Connect discovery url
     pub discovery_url: Option<String>,
 
     /// Callback URL that's been set for your application
     pub callback_url: Option<String>,
 
+    /// Opens the system browser and listens on loopback `callback_url` instead of using Chromium <https://www.rfc-editor.org/rfc/rfc8252#section-7.3>
+    pub system_browser: Option<bool>,
+
+    /// Prints the authorization url and reads the redirect pasted back by the user
+    pub no_browser: Option<bool>,
+
+    /// Time in milliseconds to paste the redirect back with `--no-browser`
+    pub no_browser_timeout: Option<u64>,
+
+    /// Browser profile directory keeping IdP sessions between runs
+    pub browser_profile_dir: Option<String>,
+
+    /// Path of a Chromium-based browser executable
+    pub browser_executable: Option<String>,
+
+    /// Extra arguments the browser is launched with
+    pub browser_args: Option<Vec<String>>,
+
+    /// DevTools websocket url of a running browser
+    pub browser_ws_url: Option<String>,
+
+    /// Remote debugging port of a running browser
+    pub browser_debug_port: Option<u16>,
+
     /// OAuth 2.0 Client Identifier <https://www.rfc-editor.org/rfc/rfc6749#section-2.2>
     pub client_id: Option<String>,
 
     /// OAuth 2.0 Client Secret. Please use `--client-secret-stdin`, because it's not get stored in a shell history.  <https://www.rfc-editor.org/rfc/rfc6749#section-2.3.1>
     pub client_secret: Option<String>,
 
+    /// Client authentication method used while calling the token endpoint <https://openid.net/specs/openid-connect-core-1_0.html#ClientAuthentication>
+    pub client_auth_method: Option<ClientAuthMethod>,
+
+    /// PEM private key file signing client assertions of `private_key_jwt` client authentication method
+    pub client_assertion_key: Option<String>,
+
+    /// Client assertion signing algorithm of `private_key_jwt` client authentication method
+    pub client_assertion_algorithm: Option<SigningAlgorithm>,
+
+    /// Client assertion `kid` header of `private_key_jwt` and `client_secret_jwt` client authentication methods
+    pub client_assertion_key_id: Option<String>,
+
+    /// Client certificate file (PEM or PKCS#12) used for Mutual-TLS <https://www.rfc-editor.org/rfc/rfc8705>
+    pub client_cert: Option<String>,
+
+    /// PEM private key file of the client certificate
+    pub client_key: Option<String>,
+
+    /// Password of PKCS#12 client certificate file
+    pub client_cert_password: Option<String>,
+
     /// OAuth 2.0 Resource Owner Password Client Credentials Grant's username <https://www.rfc-editor.org/rfc/rfc6749#section-4.3.2>
     pub username: Option<String>,
 
@@ -42,8 +117,68 @@ pub struct Profile {
     /// OpenID Connect requested aud
     pub audience: Option<String>,
 
+    /// Profile whose token cached by doken is used as OAuth 2.0 Token Exchange Grant's subject token
+    pub subject_token_profile: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's subject token type <https://www.rfc-editor.org/rfc/rfc8693#section-3>
+    pub subject_token_type: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's requested token type <https://www.rfc-editor.org/rfc/rfc8693#section-3>
+    pub requested_token_type: Option<String>,
+
+    /// OAuth 2.0 Token Exchange Grant's target resource <https://www.rfc-editor.org/rfc/rfc8693#section-2.1>
+    pub resource: Option<String>,
+
+    /// PEM private key file (or a file with a shared secret for HS256) signing JWT Bearer Grant's assertion <https://www.rfc-editor.org/rfc/rfc7523#section-2.1>
+    pub assertion_key: Option<String>,
+
+    /// JWT Bearer Grant's assertion signing algorithm
+    pub assertion_algorithm: Option<SigningAlgorithm>,
+
+    /// JWT Bearer Grant's assertion `kid` header
+    pub assertion_key_id: Option<String>,
+
+    /// JWT Bearer Grant's assertion `iss` claim <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+    pub assertion_issuer: Option<String>,
+
+    /// JWT Bearer Grant's assertion `sub` claim <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+    pub assertion_subject: Option<String>,
+
+    /// JWT Bearer Grant's assertion `aud` claim <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+    pub assertion_audience: Option<String>,
+
+    /// JWT Bearer Grant's assertion lifetime in seconds
+    pub assertion_lifetime: Option<u64>,
+
+    /// JWT Bearer Grant's assertion additional claims
+    pub assertion_claims: Option<HashMap<String, serde_json::Value>>,
+
+    /// PEM private key file (or a file with a shared secret for HS256) signing authorization request objects <https://www.rfc-editor.org/rfc/rfc9101>
+    pub request_object_key: Option<String>,
+
+    /// Authorization request object signing algorithm
+    pub request_object_algorithm: Option<SigningAlgorithm>,
+
+    /// Authorization request object `kid` header
+    pub request_object_key_id: Option<String>,
+
+    /// Binds tokens to a DPoP key <https://www.rfc-editor.org/rfc/rfc9449>
+    pub dpop: Option<bool>,
+
+    /// Token printed as an output
+    pub output: Option<Output>,
+
     /// Authorization Code, Authorization Code with PKCE and Implicit Grants' timeout,
     pub timeout: Option<u64>,
+
+    /// Minimum remaining lifetime in seconds of a cached token
+    pub min_ttl: Option<u64>,
+
+    /// Allowed clock difference in seconds between the machine and the IdP
+    pub clock_skew: Option<u64>,
+
+    /// Lifetime in seconds of tokens without expiry
+    pub default_token_lifetime: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -96,14 +231,19 @@ impl ConfigFile {
         }
     }
 
-    pub async fn apply_profile(&self, profile: Option<String>) -> Result<()> {
+    pub async fn get_profile(&self, profile: &str) -> Result<Profile> {
         let config = self.read().await;
 
+        config
+            .profile
+            .get(profile)
+            .cloned()
+            .context(format!("The given profile `{profile:?}` doesn't exist"))
+    }
+
+    pub async fn apply_profile(&self, profile: Option<String>) -> Result<()> {
         if let Some(profile) = profile {
-            let profile = config
-                .profile
-                .get(&profile)
-                .context(format!("The given profile `{profile:?}` doesn't exist"))?;
+            let profile = self.get_profile(&profile).await?;
 
             // TODO: Some macro?
             if let Some(grant) = &profile.grant {
@@ -130,12 +270,122 @@ impl ConfigFile {
                 }
             }
 
+            if let Some(device_authorization_url) = &profile.device_authorization_url {
+                unsafe {
+                    env::set_var("DOKEN_DEVICE_AUTHORIZATION_URL", device_authorization_url);
+                }
+            }
+
+            if let Some(pushed_authorization_request_url) =
+                &profile.pushed_authorization_request_url
+            {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_PUSHED_AUTHORIZATION_REQUEST_URL",
+                        pushed_authorization_request_url,
+                    );
+                }
+            }
+
+            if let Some(issuer) = &profile.issuer {
+                unsafe {
+                    env::set_var("DOKEN_ISSUER", issuer);
+                }
+            }
+
+            if let Some(jwks_url) = &profile.jwks_url {
+                unsafe {
+                    env::set_var("DOKEN_JWKS_URL", jwks_url);
+                }
+            }
+
+            if let Some(skip_id_token_validation) = &profile.skip_id_token_validation {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_SKIP_ID_TOKEN_VALIDATION",
+                        skip_id_token_validation.to_string(),
+                    );
+                }
+            }
+
+            if let Some(userinfo_url) = &profile.userinfo_url {
+                unsafe {
+                    env::set_var("DOKEN_USERINFO_URL", userinfo_url);
+                }
+            }
+
+            if let Some(revocation_url) = &profile.revocation_url {
+                unsafe {
+                    env::set_var("DOKEN_REVOCATION_URL", revocation_url);
+                }
+            }
+
+            if let Some(introspection_url) = &profile.introspection_url {
+                unsafe {
+                    env::set_var("DOKEN_INTROSPECTION_URL", introspection_url);
+                }
+            }
+
+            if let Some(par) = &profile.par {
+                unsafe {
+                    env::set_var("DOKEN_PAR", par.to_string());
+                }
+            }
+
             if let Some(callback_url) = &profile.callback_url {
                 unsafe {
                     env::set_var("DOKEN_CALLBACK_URL", callback_url);
                 }
             }
 
+            if let Some(system_browser) = &profile.system_browser {
+                unsafe {
+                    env::set_var("DOKEN_SYSTEM_BROWSER", system_browser.to_string());
+                }
+            }
+
+            if let Some(no_browser) = &profile.no_browser {
+                unsafe {
+                    env::set_var("DOKEN_NO_BROWSER", no_browser.to_string());
+                }
+            }
+
+            if let Some(no_browser_timeout) = &profile.no_browser_timeout {
+                unsafe {
+                    env::set_var("DOKEN_NO_BROWSER_TIMEOUT", no_browser_timeout.to_string());
+                }
+            }
+
+            if let Some(browser_profile_dir) = &profile.browser_profile_dir {
+                unsafe {
+                    env::set_var("DOKEN_BROWSER_PROFILE_DIR", browser_profile_dir);
+                }
+            }
+
+            if let Some(browser_executable) = &profile.browser_executable {
+                unsafe {
+                    env::set_var("DOKEN_BROWSER_EXECUTABLE", browser_executable);
+                }
+            }
+
+            if let Some(browser_args) = &profile.browser_args {
+                unsafe {
+                    env::set_var("DOKEN_BROWSER_ARGS", browser_args.join(" "));
+                }
+            }
+
+            if let Some(browser_ws_url) = &profile.browser_ws_url {
+                unsafe {
+                    env::set_var("DOKEN_BROWSER_WS_URL", browser_ws_url);
+                }
+            }
+
+            if let Some(browser_debug_port) = &profile.browser_debug_port {
+                unsafe {
+                    env::set_var("DOKEN_BROWSER_DEBUG_PORT", browser_debug_port.to_string());
+                }
+            }
+
             if let Some(client_id) = &profile.client_id {
                 unsafe {
                     env::set_var("DOKEN_CLIENT_ID", client_id);
@@ -148,6 +398,54 @@ impl ConfigFile {
                 }
             }
 
+            if let Some(client_auth_method) = &profile.client_auth_method {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_CLIENT_AUTH_METHOD",
+                        to_variant_name(&client_auth_method).unwrap(),
+                    );
+                }
+            }
+
+            if let Some(client_assertion_key) = &profile.client_assertion_key {
+                unsafe {
+                    env::set_var("DOKEN_CLIENT_ASSERTION_KEY", client_assertion_key);
+                }
+            }
+
+            if let Some(client_assertion_algorithm) = &profile.client_assertion_algorithm {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_CLIENT_ASSERTION_ALGORITHM",
+                        to_variant_name(&client_assertion_algorithm).unwrap(),
+                    );
+                }
+            }
+
+            if let Some(client_assertion_key_id) = &profile.client_assertion_key_id {
+                unsafe {
+                    env::set_var("DOKEN_CLIENT_ASSERTION_KEY_ID", client_assertion_key_id);
+                }
+            }
+
+            if let Some(client_cert) = &profile.client_cert {
+                unsafe {
+                    env::set_var("DOKEN_CLIENT_CERT", client_cert);
+                }
+            }
+
+            if let Some(client_key) = &profile.client_key {
+                unsafe {
+                    env::set_var("DOKEN_CLIENT_KEY", client_key);
+                }
+            }
+
+            if let Some(client_cert_password) = &profile.client_cert_password {
+                unsafe {
+                    env::set_var("DOKEN_CLIENT_CERT_PASSWORD", client_cert_password);
+                }
+            }
+
             if let Some(username) = &profile.username {
                 unsafe {
                     env::set_var("DOKEN_USERNAME", username);
@@ -172,11 +470,143 @@ impl ConfigFile {
                 }
             }
 
+            if let Some(subject_token_profile) = &profile.subject_token_profile {
+                unsafe {
+                    env::set_var("DOKEN_SUBJECT_TOKEN_PROFILE", subject_token_profile);
+                }
+            }
+
+            if let Some(subject_token_type) = &profile.subject_token_type {
+                unsafe {
+                    env::set_var("DOKEN_SUBJECT_TOKEN_TYPE", subject_token_type);
+                }
+            }
+
+            if let Some(requested_token_type) = &profile.requested_token_type {
+                unsafe {
+                    env::set_var("DOKEN_REQUESTED_TOKEN_TYPE", requested_token_type);
+                }
+            }
+
+            if let Some(resource) = &profile.resource {
+                unsafe {
+                    env::set_var("DOKEN_RESOURCE", resource);
+                }
+            }
+
+            if let Some(assertion_key) = &profile.assertion_key {
+                unsafe {
+                    env::set_var("DOKEN_ASSERTION_KEY", assertion_key);
+                }
+            }
+
+            if let Some(assertion_algorithm) = &profile.assertion_algorithm {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_ASSERTION_ALGORITHM",
+                        to_variant_name(&assertion_algorithm).unwrap(),
+                    );
+                }
+            }
+
+            if let Some(assertion_key_id) = &profile.assertion_key_id {
+                unsafe {
+                    env::set_var("DOKEN_ASSERTION_KEY_ID", assertion_key_id);
+                }
+            }
+
+            if let Some(assertion_issuer) = &profile.assertion_issuer {
+                unsafe {
+                    env::set_var("DOKEN_ASSERTION_ISSUER", assertion_issuer);
+                }
+            }
+
+            if let Some(assertion_subject) = &profile.assertion_subject {
+                unsafe {
+                    env::set_var("DOKEN_ASSERTION_SUBJECT", assertion_subject);
+                }
+            }
+
+            if let Some(assertion_audience) = &profile.assertion_audience {
+                unsafe {
+                    env::set_var("DOKEN_ASSERTION_AUDIENCE", assertion_audience);
+                }
+            }
+
+            if let Some(assertion_lifetime) = &profile.assertion_lifetime {
+                unsafe {
+                    env::set_var("DOKEN_ASSERTION_LIFETIME", assertion_lifetime.to_string());
+                }
+            }
+
+            if let Some(assertion_claims) = &profile.assertion_claims {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_ASSERTION_CLAIMS",
+                        serde_json::to_string(assertion_claims)?,
+                    );
+                }
+            }
+
+            if let Some(request_object_key) = &profile.request_object_key {
+                unsafe {
+                    env::set_var("DOKEN_REQUEST_OBJECT_KEY", request_object_key);
+                }
+            }
+
+            if let Some(request_object_algorithm) = &profile.request_object_algorithm {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_REQUEST_OBJECT_ALGORITHM",
+                        to_variant_name(&request_object_algorithm).unwrap(),
+                    );
+                }
+            }
+
+            if let Some(request_object_key_id) = &profile.request_object_key_id {
+                unsafe {
+                    env::set_var("DOKEN_REQUEST_OBJECT_KEY_ID", request_object_key_id);
+                }
+            }
+
+            if let Some(dpop) = &profile.dpop {
+                unsafe {
+                    env::set_var("DOKEN_DPOP", dpop.to_string());
+                }
+            }
+
+            if let Some(output) = &profile.output {
+                unsafe {
+                    env::set_var("DOKEN_OUTPUT", to_variant_name(&output).unwrap());
+                }
+            }
+
             if let Some(timeout) = &profile.timeout {
                 unsafe {
                     env::set_var("DOKEN_TIMEOUT", timeout.to_string());
                 }
             }
+
+            if let Some(min_ttl) = &profile.min_ttl {
+                unsafe {
+                    env::set_var("DOKEN_MIN_TTL", min_ttl.to_string());
+                }
+            }
+
+            if let Some(clock_skew) = &profile.clock_skew {
+                unsafe {
+                    env::set_var("DOKEN_CLOCK_SKEW", clock_skew.to_string());
+                }
+            }
+
+            if let Some(default_token_lifetime) = &profile.default_token_lifetime {
+                unsafe {
+                    env::set_var(
+                        "DOKEN_DEFAULT_TOKEN_LIFETIME",
+                        default_token_lifetime.to_string(),
+                    );
+                }
+            }
         }
 
         Ok(())
@@ -0,0 +1,126 @@
+use crate::file_state::FileState;
+use crate::jwt::{self, Claims};
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk,
+};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+const DPOP_JWT_TYPE: &str = "dpop+jwt";
+
+/// ES256 key pair proving the possession of DPoP-bound tokens. More: <https://www.rfc-editor.org/rfc/rfc9449>
+pub struct DPoPKey {
+    pkcs8: Vec<u8>,
+    x: String,
+    y: String,
+}
+
+impl DPoPKey {
+    fn from_pkcs8(pkcs8: Vec<u8>) -> Result<DPoPKey> {
+        let key_pair = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            &pkcs8,
+            &SystemRandom::new(),
+        )
+        .map_err(|e| anyhow!("DPoP key saved in the state is malformed: {e}"))?;
+
+        // NOTE: Public key is an uncompressed point 0x04 || x || y
+        let (x, y) = key_pair.public_key().as_ref()[1..].split_at(32);
+
+        Ok(DPoPKey {
+            x: BASE64_URL_SAFE_NO_PAD.encode(x),
+            y: BASE64_URL_SAFE_NO_PAD.encode(y),
+            pkcs8,
+        })
+    }
+
+    /// Reads the key from the state or generates and saves a new one
+    pub fn from_state(file_state: &mut FileState) -> Result<DPoPKey> {
+        if let Some(key) = file_state.read_dpop_key() {
+            let pkcs8 = BASE64_URL_SAFE_NO_PAD
+                .decode(key)
+                .context("DPoP key saved in the state is malformed")?;
+
+            return Self::from_pkcs8(pkcs8);
+        }
+
+        log::debug!("Generating a new DPoP key");
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .map_err(|_| anyhow!("Failed to generate a DPoP key"))?;
+
+        file_state.upsert_dpop_key(BASE64_URL_SAFE_NO_PAD.encode(pkcs8.as_ref()))?;
+
+        Self::from_pkcs8(pkcs8.as_ref().to_vec())
+    }
+
+    fn jwk(&self) -> Jwk {
+        Jwk {
+            common: CommonParameters::default(),
+            algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: EllipticCurveKeyType::EC,
+                curve: EllipticCurve::P256,
+                x: self.x.to_owned(),
+                y: self.y.to_owned(),
+            }),
+        }
+    }
+
+    /// JWK SHA-256 thumbprint of the public key. More: <https://www.rfc-editor.org/rfc/rfc7638>
+    pub fn thumbprint(&self) -> String {
+        // NOTE: Required members in lexicographic order without whitespaces
+        let jwk = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            self.x, self.y
+        );
+
+        BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(jwk.as_bytes()))
+    }
+
+    /// Signs a proof of a single HTTP request. More: <https://www.rfc-editor.org/rfc/rfc9449#section-4.2>
+    pub fn proof(
+        &self,
+        method: &str,
+        url: &str,
+        nonce: Option<&str>,
+        access_token: Option<&str>,
+    ) -> Result<String> {
+        let mut htu =
+            Url::parse(url).with_context(|| format!("{url} is not a correct absolute URL"))?;
+        htu.set_query(None);
+        htu.set_fragment(None);
+
+        let mut claims = Claims::new();
+        claims.insert("jti".to_owned(), jwt::jti().into());
+        claims.insert("htm".to_owned(), method.to_uppercase().into());
+        claims.insert("htu".to_owned(), htu.as_str().into());
+        claims.insert("iat".to_owned(), jwt::now().into());
+
+        if let Some(nonce) = nonce {
+            claims.insert("nonce".to_owned(), nonce.into());
+        }
+
+        if let Some(access_token) = access_token {
+            claims.insert(
+                "ath".to_owned(),
+                BASE64_URL_SAFE_NO_PAD
+                    .encode(Sha256::digest(access_token.as_bytes()))
+                    .into(),
+            );
+        }
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.typ = Some(DPOP_JWT_TYPE.to_owned());
+        header.jwk = Some(self.jwk());
+
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_ec_der(&self.pkcs8))
+            .context("Failed to sign a DPoP proof")
+    }
+}
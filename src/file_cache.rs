@@ -0,0 +1,22 @@
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Path of a file in ~/.doken/cache directory keeping a response of the url
+pub fn cache_file_path(prefix: &str, url: &str) -> PathBuf {
+    let file_name = format!(
+        "{prefix}-{}.json",
+        BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(url.as_bytes()))
+    );
+
+    match home::home_dir() {
+        Some(mut home_dir) => {
+            home_dir.push(".doken/cache");
+            home_dir.push(file_name);
+            home_dir
+        }
+
+        None => panic!("Couldn't access $HOME_DIR"),
+    }
+}
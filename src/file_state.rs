@@ -1,4 +1,5 @@
 use crate::token_info::TokenInfo;
+use crate::token_key::TokenKey;
 use anyhow::{Context, Result};
 use file_guard::{FileGuard, Lock};
 use serde::{Deserialize, Serialize};
@@ -8,12 +9,17 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::{collections::HashMap, fs::File};
 
-type ClientId = String;
+const STATE_VERSION: u32 = 2;
 
 #[derive(Deserialize, Serialize)]
 struct DokenState {
     version: u32,
-    data: HashMap<ClientId, TokenInfo>,
+    /// Tokens by `TokenKey`. Version 1 used client_id as a key
+    data: HashMap<String, TokenInfo>,
+
+    /// Base64url encoded PKCS#8 ES256 key pair used for DPoP proofs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dpop_key: Option<String>,
 }
 
 pub struct FileState {
@@ -79,7 +85,30 @@ impl FileState {
         let _ = self.file.read_to_string(&mut text);
 
         let data = HashMap::new();
-        serde_json::from_str::<DokenState>(&text).unwrap_or(DokenState { version: 1, data })
+        let mut state = serde_json::from_str::<DokenState>(&text).unwrap_or(DokenState {
+            version: STATE_VERSION,
+            data,
+            dpop_key: None,
+        });
+
+        // NOTE: Tokens of version 1 are keyed by client_id only. They are keyed by the scope
+        // given with them too, while the issuer and the grant stay unknown
+        if state.version < STATE_VERSION {
+            log::debug!("Migrating the state from version {}", state.version);
+
+            state.data = state
+                .data
+                .into_iter()
+                .map(|(client_id, token_info)| {
+                    let key = TokenKey::from_version_1(&client_id, token_info.scope.as_deref());
+
+                    (key.to_string(), token_info)
+                })
+                .collect();
+            state.version = STATE_VERSION;
+        }
+
+        state
     }
 
     fn write(&mut self, state: &DokenState) -> Result<()> {
@@ -96,29 +125,78 @@ impl FileState {
         Ok(())
     }
 
-    pub fn read_token_info(&mut self, client_id: &String) -> Option<TokenInfo> {
-        log::debug!("Reading token info for client_id: {client_id} from the state",);
+    pub fn read_token_info(&mut self, key: &TokenKey) -> Option<TokenInfo> {
+        log::debug!("Reading token info for key: {key} from the state",);
+        let mut state = self.read();
+
+        if let Some(token_info) = state.data.get(&key.to_string()) {
+            return Some(token_info.to_owned());
+        }
+
+        // NOTE: Token migrated from version 1 is moved under the key of the first request
+        // matching it, which makes its issuer and grant known
+        let token_info = state.data.remove(&key.to_version_1().to_string())?;
+        log::debug!("Adopting token of version 1 of the state for key: {key}");
+
+        state.data.insert(key.to_string(), token_info.to_owned());
+        if let Err(e) = self.write(&state) {
+            log::warn!("Couldn't save the adopted token: {e:#}");
+        }
+
+        Some(token_info)
+    }
+
+    pub fn read_all_token_info(&mut self) -> Vec<(TokenKey, TokenInfo)> {
+        log::debug!("Reading all token info from the state");
         let state = self.read();
 
-        state.data.get(client_id).cloned()
+        state
+            .data
+            .into_iter()
+            .filter_map(|(key, token_info)| Some((key.parse::<TokenKey>().ok()?, token_info)))
+            .collect()
+    }
+
+    /// Tokens requested with different scopes, but otherwise the same way as the key's one
+    pub fn read_sibling_token_info(&mut self, key: &TokenKey) -> Vec<(TokenKey, TokenInfo)> {
+        self.read_all_token_info()
+            .into_iter()
+            .filter(|(other, _)| other != key && other.is_sibling_of(key))
+            .collect()
+    }
+
+    pub fn upsert_token_info(&mut self, key: &TokenKey, token_info: TokenInfo) -> Result<()> {
+        log::debug!("Saving token info: {token_info:#?} for key: {key} to the state",);
+        let mut state = self.read();
+
+        state.data.insert(key.to_string(), token_info);
+
+        self.write(&state)?;
+
+        Ok(())
+    }
+
+    pub fn read_dpop_key(&mut self) -> Option<String> {
+        log::debug!("Reading DPoP key from the state");
+        self.read().dpop_key
     }
 
-    pub fn upsert_token_info(&mut self, client_id: String, token_info: TokenInfo) -> Result<()> {
-        log::debug!("Saving token info: {token_info:#?} for client_id: {client_id} to the state",);
+    pub fn upsert_dpop_key(&mut self, dpop_key: String) -> Result<()> {
+        log::debug!("Saving DPoP key to the state");
         let mut state = self.read();
 
-        state.data.insert(client_id, token_info);
+        state.dpop_key = Some(dpop_key);
 
         self.write(&state)?;
 
         Ok(())
     }
 
-    pub fn clear_token_info(&mut self, client_id: String) -> Result<()> {
-        log::debug!("Clearing token info for client_id: {client_id} in the state",);
+    pub fn clear_token_info(&mut self, key: &TokenKey) -> Result<()> {
+        log::debug!("Clearing token info for key: {key} in the state",);
         let mut state = self.read();
 
-        state.data.remove(&client_id);
+        state.data.remove(&key.to_string());
 
         self.write(&state)?;
 
@@ -135,6 +213,7 @@ mod tests {
     use tempfile::TempDir;
 
     use super::*;
+    use crate::grant::Grant;
 
     fn get_tmp_path() -> Result<(TempDir, PathBuf)> {
         let tmp_dir = tempfile::tempdir()?;
@@ -147,20 +226,34 @@ mod tests {
         s.replace([' ', '\n'], "")
     }
 
+    fn get_key(client_id: &str) -> TokenKey {
+        TokenKey::new(
+            Some("https://my-idp.com"),
+            client_id,
+            &Grant::ClientCredentials,
+            "profile email",
+            None,
+            None,
+        )
+    }
+
     #[test]
     fn it_writes_state_to_file() {
         let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
-        const CLIENT_ID: &str = "test-client-id";
+        let key = get_key("test-client-id");
 
         file_state
             .upsert_token_info(
-                CLIENT_ID.to_owned(),
+                &key,
                 TokenInfo {
                     access_token: "test-access-token".to_owned(),
                     refresh_token: None,
+                    id_token: None,
                     expires: None,
                     scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
@@ -168,9 +261,9 @@ mod tests {
         let content = fs::read_to_string(tmp_path).unwrap_or_default();
 
         let expected = r#"{
-  "version": 1,
+  "version": 2,
   "data": {
-    "test-client-id": {
+    "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id&grant=client-credentials&scope=email+profile": {
       "access_token": "test-access-token",
       "refresh_token": null,
       "expires": null,
@@ -186,16 +279,19 @@ mod tests {
     fn it_writes_state_to_file_with_all_possible_values() {
         let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
-        const CLIENT_ID: &str = "test-client-id";
+        let key = get_key("test-client-id");
 
         file_state
             .upsert_token_info(
-                CLIENT_ID.to_owned(),
+                &key,
                 TokenInfo {
                     access_token: "test-access-token".to_owned(),
                     refresh_token: Some("test-refresh-token".to_owned()),
+                    id_token: None,
                     expires: Some(SystemTime::UNIX_EPOCH),
                     scope: Some("email-profile".to_owned()),
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
@@ -203,9 +299,9 @@ mod tests {
         let content = fs::read_to_string(tmp_path).unwrap_or_default();
 
         let expected = r#"{
-  "version": 1,
+  "version": 2,
   "data": {
-    "test-client-id": {
+    "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id&grant=client-credentials&scope=email+profile": {
       "access_token": "test-access-token",
       "refresh_token": "test-refresh-token",
       "expires": {
@@ -220,31 +316,147 @@ mod tests {
         assert_eq!(content, uglify(expected));
     }
 
+    #[test]
+    fn it_writes_certificate_thumbprint_of_bound_token() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let key = get_key("test-client-id");
+
+        file_state
+            .upsert_token_info(
+                &key,
+                TokenInfo {
+                    access_token: "test-access-token".to_owned(),
+                    refresh_token: None,
+                    id_token: None,
+                    expires: None,
+                    scope: None,
+                    token_type: None,
+                    cert_thumbprint: Some("test-thumbprint".to_owned()),
+                },
+            )
+            .unwrap();
+
+        let content = fs::read_to_string(tmp_path).unwrap_or_default();
+
+        let expected = r#"{
+  "version": 2,
+  "data": {
+    "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id&grant=client-credentials&scope=email+profile": {
+      "access_token": "test-access-token",
+      "refresh_token": null,
+      "expires": null,
+      "scope": null,
+      "cert_thumbprint": "test-thumbprint"
+    }
+  }
+}"#;
+
+        assert_eq!(content, uglify(expected));
+    }
+
+    #[test]
+    fn it_keeps_dpop_key_next_to_tokens() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let key = get_key("test-client-id");
+
+        file_state
+            .upsert_dpop_key("test-dpop-key".to_owned())
+            .unwrap();
+        file_state
+            .upsert_token_info(
+                &key,
+                TokenInfo {
+                    access_token: "test-access-token".to_owned(),
+                    refresh_token: None,
+                    id_token: None,
+                    expires: None,
+                    scope: None,
+                    token_type: Some("DPoP".to_owned()),
+                    cert_thumbprint: None,
+                },
+            )
+            .unwrap();
+
+        let content = fs::read_to_string(tmp_path).unwrap_or_default();
+
+        let expected = r#"{
+  "version": 2,
+  "data": {
+    "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id&grant=client-credentials&scope=email+profile": {
+      "access_token": "test-access-token",
+      "refresh_token": null,
+      "expires": null,
+      "scope": null,
+      "token_type": "DPoP"
+    }
+  },
+  "dpop_key": "test-dpop-key"
+}"#;
+
+        assert_eq!(content, uglify(expected));
+        assert_eq!(file_state.read_dpop_key(), Some("test-dpop-key".to_owned()));
+    }
+
+    #[test]
+    fn it_reads_id_token_from_state() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let key = get_key("test-client-id");
+
+        file_state
+            .upsert_token_info(
+                &key,
+                TokenInfo {
+                    access_token: "test-access-token".to_owned(),
+                    refresh_token: None,
+                    id_token: Some("test-id-token".to_owned()),
+                    expires: None,
+                    scope: None,
+                    token_type: Some("Bearer".to_owned()),
+                    cert_thumbprint: None,
+                },
+            )
+            .unwrap();
+
+        let token_info = file_state.read_token_info(&key).unwrap();
+
+        assert_eq!(token_info.id_token, Some("test-id-token".to_owned()));
+        assert_eq!(token_info.token_type, Some("Bearer".to_owned()));
+    }
+
     #[test]
     fn it_overwrites_state_of_client_id() {
         let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
-        const CLIENT_ID: &str = "test-client-id";
+        let key = get_key("test-client-id");
 
         file_state
             .upsert_token_info(
-                CLIENT_ID.to_owned(),
+                &key,
                 TokenInfo {
                     access_token: "not-important".to_owned(),
                     refresh_token: None,
+                    id_token: None,
                     expires: None,
                     scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
         file_state
             .upsert_token_info(
-                CLIENT_ID.to_owned(),
+                &key,
                 TokenInfo {
                     access_token: "test-access-token-overwrite".to_owned(),
                     refresh_token: None,
+                    id_token: None,
                     expires: None,
                     scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
@@ -252,9 +464,9 @@ mod tests {
         let content = fs::read_to_string(tmp_path).unwrap_or_default();
 
         let expected = r#"{
-  "version": 1,
+  "version": 2,
   "data": {
-    "test-client-id": {
+    "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id&grant=client-credentials&scope=email+profile": {
       "access_token": "test-access-token-overwrite",
       "refresh_token": null,
       "expires": null,
@@ -270,26 +482,29 @@ mod tests {
     fn it_removes_client_id_data() {
         let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
-        const CLIENT_ID: &str = "test-client-id";
+        let key = get_key("test-client-id");
 
         file_state
             .upsert_token_info(
-                CLIENT_ID.to_owned(),
+                &key,
                 TokenInfo {
                     access_token: "not-important".to_owned(),
                     refresh_token: None,
+                    id_token: None,
                     expires: None,
                     scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
 
-        file_state.clear_token_info(CLIENT_ID.to_owned()).unwrap();
+        file_state.clear_token_info(&key).unwrap();
 
         let content = fs::read_to_string(tmp_path).unwrap_or_default();
 
         let expected = r#"{
-  "version": 1,
+  "version": 2,
   "data": {}
 }"#;
 
@@ -302,13 +517,13 @@ mod tests {
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
 
         file_state
-            .clear_token_info("test-client-id".to_owned())
+            .clear_token_info(&get_key("test-client-id"))
             .unwrap();
 
         let content = fs::read_to_string(tmp_path).unwrap_or_default();
 
         let expected = r#"{
-  "version": 1,
+  "version": 2,
   "data": {}
 }"#;
 
@@ -319,30 +534,33 @@ mod tests {
     fn it_does_not_change_other_client_id_state() {
         let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
-        const CLIENT_ID: &str = "test-client-id-10";
+        let key = get_key("test-client-id-10");
 
         file_state
             .upsert_token_info(
-                CLIENT_ID.to_owned(),
+                &key,
                 TokenInfo {
                     access_token: "test-access-token-another-one".to_owned(),
                     refresh_token: None,
+                    id_token: None,
                     expires: None,
                     scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
 
         file_state
-            .clear_token_info("test-client-id-that-does-not-exist".to_owned())
+            .clear_token_info(&get_key("test-client-id-that-does-not-exist"))
             .unwrap();
 
         let content = fs::read_to_string(tmp_path).unwrap_or_default();
 
         let expected = r#"{
-  "version": 1,
+  "version": 2,
   "data": {
-    "test-client-id-10": {
+    "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id-10&grant=client-credentials&scope=email+profile": {
       "access_token": "test-access-token-another-one",
       "refresh_token": null,
       "expires": null,
@@ -358,31 +576,37 @@ mod tests {
     fn it_reads_state_of_correct_client_id() {
         let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
         let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
-        const CLIENT_ID: &str = "test-client-id";
+        let key = get_key("test-client-id");
 
         let expected_token_info = TokenInfo {
             access_token: "test-access-token".to_owned(),
             refresh_token: Some("test-refresh-token".to_owned()),
+            id_token: None,
             expires: Some(SystemTime::UNIX_EPOCH),
             scope: Some("email-profile".to_owned()),
+            token_type: None,
+            cert_thumbprint: None,
         };
 
         file_state
             .upsert_token_info(
-                "not-important".to_owned(),
+                &get_key("not-important"),
                 TokenInfo {
                     access_token: "not-important".to_owned(),
                     refresh_token: None,
+                    id_token: None,
                     expires: None,
                     scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
                 },
             )
             .unwrap();
         file_state
-            .upsert_token_info(CLIENT_ID.to_owned(), expected_token_info.to_owned())
+            .upsert_token_info(&key, expected_token_info.to_owned())
             .unwrap();
 
-        let actual_token_info = file_state.read_token_info(&CLIENT_ID.to_owned()).unwrap();
+        let actual_token_info = file_state.read_token_info(&key).unwrap();
 
         assert_eq!(
             actual_token_info.access_token,
@@ -395,4 +619,247 @@ mod tests {
         assert_eq!(actual_token_info.expires, expected_token_info.expires);
         assert_eq!(actual_token_info.scope, expected_token_info.scope);
     }
+
+    #[test]
+    fn it_does_not_share_tokens_between_different_keys() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let key = get_key("test-client-id");
+        let other_audience_key = TokenKey::new(
+            Some("https://my-idp.com"),
+            "test-client-id",
+            &Grant::ClientCredentials,
+            "email profile",
+            Some("https://other-api.com"),
+            None,
+        );
+        let reordered_scope_key = TokenKey::new(
+            Some("https://my-idp.com"),
+            "test-client-id",
+            &Grant::ClientCredentials,
+            "email  profile email",
+            None,
+            None,
+        );
+
+        file_state
+            .upsert_token_info(
+                &key,
+                TokenInfo {
+                    access_token: "test-access-token".to_owned(),
+                    refresh_token: None,
+                    id_token: None,
+                    expires: None,
+                    scope: None,
+                    token_type: None,
+                    cert_thumbprint: None,
+                },
+            )
+            .unwrap();
+
+        assert!(file_state.read_token_info(&other_audience_key).is_none());
+        assert_eq!(
+            file_state
+                .read_token_info(&reordered_scope_key)
+                .unwrap()
+                .access_token,
+            "test-access-token"
+        );
+    }
+
+    fn write_version_1_state(path: &PathBuf) {
+        fs::write(
+            path,
+            r#"{
+  "version": 1,
+  "data": {
+    "test-client-id": {
+      "access_token": "test-access-token",
+      "refresh_token": "test-refresh-token",
+      "expires": null,
+      "scope": "profile email"
+    },
+    "other-client-id": {
+      "access_token": "other-access-token",
+      "refresh_token": null,
+      "expires": null,
+      "scope": null
+    }
+  }
+}"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn it_migrates_tokens_of_version_1_state() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        write_version_1_state(&tmp_path);
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+
+        let mut keys: Vec<String> = file_state
+            .read_all_token_info()
+            .into_iter()
+            .map(|(key, _)| key.to_string())
+            .collect();
+        keys.sort();
+
+        assert_eq!(
+            keys,
+            [
+                "client_id=other-client-id&scope=offline_access",
+                "client_id=test-client-id&scope=email+profile",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_adopts_token_of_version_1_state_by_matching_request() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        write_version_1_state(&tmp_path);
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let key = get_key("test-client-id");
+
+        let token_info = file_state.read_token_info(&key).unwrap();
+
+        assert_eq!(token_info.access_token, "test-access-token");
+        assert_eq!(
+            token_info.refresh_token.as_deref(),
+            Some("test-refresh-token")
+        );
+
+        let content = fs::read_to_string(tmp_path).unwrap_or_default();
+        let state = serde_json::from_str::<DokenState>(&content).unwrap();
+        let mut keys: Vec<&String> = state.data.keys().collect();
+        keys.sort();
+
+        assert_eq!(state.version, 2);
+        assert_eq!(
+            keys,
+            [
+                "client_id=other-client-id&scope=offline_access",
+                "issuer=https%3A%2F%2Fmy-idp.com&client_id=test-client-id&grant=client-credentials&scope=email+profile",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_keeps_token_of_version_1_state_from_requests_of_other_scope() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        write_version_1_state(&tmp_path);
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let key = TokenKey::new(
+            Some("https://my-idp.com"),
+            "test-client-id",
+            &Grant::ClientCredentials,
+            "email",
+            None,
+            None,
+        );
+
+        assert!(file_state.read_token_info(&key).is_none());
+        assert!(
+            file_state
+                .read_token_info(&get_key("other-client-id"))
+                .is_none()
+        );
+        assert_eq!(file_state.read_all_token_info().len(), 2);
+    }
+
+    #[test]
+    fn it_clears_all_tokens_of_the_client() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let other_scope_key = TokenKey::new(
+            Some("https://my-idp.com"),
+            "test-client-id",
+            &Grant::ClientCredentials,
+            "openid",
+            None,
+            None,
+        );
+        let token_info = TokenInfo {
+            access_token: "test-access-token".to_owned(),
+            refresh_token: None,
+            id_token: None,
+            expires: None,
+            scope: None,
+            token_type: None,
+            cert_thumbprint: None,
+        };
+        file_state
+            .upsert_token_info(&get_key("test-client-id"), token_info.to_owned())
+            .unwrap();
+        file_state
+            .upsert_token_info(&other_scope_key, token_info.to_owned())
+            .unwrap();
+        file_state
+            .upsert_token_info(&get_key("other-client-id"), token_info)
+            .unwrap();
+
+        let client = get_key("test-client-id");
+        let entries: Vec<TokenKey> = file_state
+            .read_all_token_info()
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.is_of_client(&client))
+            .collect();
+
+        assert_eq!(entries.len(), 2);
+
+        for key in entries {
+            file_state.clear_token_info(&key).unwrap();
+        }
+
+        let remaining = file_state.read_all_token_info();
+
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].0, get_key("other-client-id"));
+    }
+
+    #[test]
+    fn it_reads_tokens_requested_with_other_scopes() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path.to_owned()).unwrap();
+        let token_info = TokenInfo {
+            access_token: "test-access-token".to_owned(),
+            refresh_token: Some("test-refresh-token".to_owned()),
+            id_token: None,
+            expires: None,
+            scope: Some("openid email profile".to_owned()),
+            token_type: None,
+            cert_thumbprint: None,
+        };
+        let get_scoped_key = |scope: &str| {
+            TokenKey::new(
+                Some("https://my-idp.com"),
+                "test-client-id",
+                &Grant::ClientCredentials,
+                scope,
+                None,
+                None,
+            )
+        };
+        file_state
+            .upsert_token_info(&get_scoped_key("openid email"), token_info.to_owned())
+            .unwrap();
+        file_state
+            .upsert_token_info(&get_key("other-client-id"), token_info.to_owned())
+            .unwrap();
+
+        let requested = get_scoped_key("openid profile");
+        let siblings = file_state.read_sibling_token_info(&requested);
+
+        assert_eq!(siblings.len(), 1);
+        assert_eq!(siblings[0].0, get_scoped_key("openid email"));
+        assert!(crate::token_key::covers_scope(
+            siblings[0].1.granted_scope(&siblings[0].0),
+            requested.scope()
+        ));
+        assert!(
+            file_state
+                .read_sibling_token_info(&get_scoped_key("email openid"))
+                .is_empty()
+        );
+    }
 }
@@ -1,6 +1,10 @@
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
+pub const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+pub const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
 #[derive(Serialize, Deserialize, ValueEnum, Clone, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub enum Grant {
@@ -14,4 +18,25 @@ pub enum Grant {
     ResourceOwnerPasswordClientCredentials,
     /// Client credentials Grant. More: <https://www.rfc-editor.org/rfc/rfc6749#section-4.4>
     ClientCredentials,
+    /// Device Authorization Grant. More: <https://www.rfc-editor.org/rfc/rfc8628>
+    DeviceCode,
+    /// Token Exchange Grant. More: <https://www.rfc-editor.org/rfc/rfc8693>
+    TokenExchange,
+    /// JWT Bearer Grant. More: <https://www.rfc-editor.org/rfc/rfc7523#section-2.1>
+    JwtBearer,
+}
+
+impl Grant {
+    /// Value of the grant in the provider's metadata. More: <https://www.rfc-editor.org/rfc/rfc8414#section-2>
+    pub fn grant_type(&self) -> &'static str {
+        match self {
+            Grant::AuthorizationCodeWithPkce | Grant::AuthorizationCode => "authorization_code",
+            Grant::Implicit => "implicit",
+            Grant::ResourceOwnerPasswordClientCredentials => "password",
+            Grant::ClientCredentials => "client_credentials",
+            Grant::DeviceCode => DEVICE_CODE_GRANT_TYPE,
+            Grant::TokenExchange => TOKEN_EXCHANGE_GRANT_TYPE,
+            Grant::JwtBearer => JWT_BEARER_GRANT_TYPE,
+        }
+    }
 }
@@ -0,0 +1,141 @@
+use crate::dpop::DPoPKey;
+use oauth2::{AsyncHttpClient, HttpClientError, HttpRequest, HttpResponse};
+use reqwest::header::HeaderValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+const DPOP_HEADER: &str = "DPoP";
+const DPOP_NONCE_HEADER: &str = "DPoP-Nonce";
+
+type Error = HttpClientError<reqwest::Error>;
+
+/// HTTP client of the provider's endpoints. Attaches DPoP proofs to requests when a key is given
+pub struct HttpClient {
+    inner: reqwest::Client,
+    dpop_key: Option<DPoPKey>,
+    dpop_nonce: Mutex<Option<String>>,
+}
+
+impl HttpClient {
+    pub fn new(inner: reqwest::Client, dpop_key: Option<DPoPKey>) -> HttpClient {
+        HttpClient {
+            inner,
+            dpop_key,
+            dpop_nonce: Mutex::new(None),
+        }
+    }
+
+    pub fn dpop_key(&self) -> Option<&DPoPKey> {
+        self.dpop_key.as_ref()
+    }
+
+    pub fn get(&self, url: url::Url) -> reqwest::RequestBuilder {
+        self.inner.get(url)
+    }
+
+    pub fn post(&self, url: url::Url) -> reqwest::RequestBuilder {
+        self.inner.post(url)
+    }
+
+    fn with_proof(
+        &self,
+        key: &DPoPKey,
+        mut request: reqwest::Request,
+        nonce: Option<&str>,
+        access_token: Option<&str>,
+    ) -> Result<reqwest::Request, Error> {
+        let proof = key
+            .proof(
+                request.method().as_str(),
+                request.url().as_str(),
+                nonce,
+                access_token,
+            )
+            .map_err(|e| HttpClientError::Other(e.to_string()))?;
+
+        request.headers_mut().insert(
+            DPOP_HEADER,
+            HeaderValue::from_str(&proof).map_err(|e| HttpClientError::Other(e.to_string()))?,
+        );
+
+        Ok(request)
+    }
+
+    /// Sends a request. With DPoP enabled it's retried once when the server demands a new nonce.
+    /// More: <https://www.rfc-editor.org/rfc/rfc9449#section-8>
+    pub async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response, Error> {
+        self.execute_with_access_token(request, None).await
+    }
+
+    /// Sends a request authorized with the access token, whose hash is included in DPoP proofs
+    pub async fn execute_with_access_token(
+        &self,
+        request: reqwest::Request,
+        access_token: Option<&str>,
+    ) -> Result<reqwest::Response, Error> {
+        let Some(key) = &self.dpop_key else {
+            return Ok(self.inner.execute(request).await.map_err(Box::new)?);
+        };
+
+        let retry_request = request.try_clone();
+        let nonce = self.dpop_nonce.lock().unwrap().to_owned();
+
+        let response = self
+            .inner
+            .execute(self.with_proof(key, request, nonce.as_deref(), access_token)?)
+            .await
+            .map_err(Box::new)?;
+
+        let new_nonce = response
+            .headers()
+            .get(DPOP_NONCE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+
+        let Some(new_nonce) = new_nonce else {
+            return Ok(response);
+        };
+
+        *self.dpop_nonce.lock().unwrap() = Some(new_nonce.to_owned());
+
+        match retry_request {
+            Some(retry_request)
+                if response.status().is_client_error() && nonce.as_ref() != Some(&new_nonce) =>
+            {
+                log::debug!("Retrying the request with DPoP nonce given by the server");
+
+                Ok(self
+                    .inner
+                    .execute(self.with_proof(key, retry_request, Some(&new_nonce), access_token)?)
+                    .await
+                    .map_err(Box::new)?)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+impl<'c> AsyncHttpClient<'c> for HttpClient {
+    type Error = Error;
+
+    type Future = Pin<Box<dyn Future<Output = Result<HttpResponse, Self::Error>> + 'c>>;
+
+    fn call(&'c self, request: HttpRequest) -> Self::Future {
+        Box::pin(async move {
+            let response = self.execute(request.try_into().map_err(Box::new)?).await?;
+
+            let mut builder = oauth2::http::Response::builder()
+                .status(response.status())
+                .version(response.version());
+
+            for (name, value) in response.headers().iter() {
+                builder = builder.header(name, value);
+            }
+
+            builder
+                .body(response.bytes().await.map_err(Box::new)?.to_vec())
+                .map_err(HttpClientError::Http)
+        })
+    }
+}
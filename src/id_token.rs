@@ -0,0 +1,349 @@
+use crate::jwks::JwksCache;
+use crate::jwt::{self, Claims};
+use anyhow::Result;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use oauth2::ExtraTokenFields;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use thiserror::Error;
+
+/// Token response fields added by OpenID Connect. More: <https://openid.net/specs/openid-connect-core-1_0.html#TokenResponse>
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct IdTokenFields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+#[derive(Error, Debug)]
+pub enum IdTokenError {
+    #[error("ID token is invalid: {0}")]
+    Invalid(#[from] jsonwebtoken::errors::Error),
+    #[error("ID token signing key `{0}` cannot be found in JWKS")]
+    KeyNotFound(String),
+    #[error("ID token is missing `{0}` claim")]
+    MissingClaim(&'static str),
+    #[error("ID token `{0}` claim doesn't match")]
+    ClaimMismatch(&'static str),
+}
+
+/// Values the ID token is checked against. More: <https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation>
+pub struct IdTokenValidation<'a> {
+    pub issuer: &'a str,
+    pub jwks_url: &'a str,
+    pub client_id: &'a str,
+    pub client_secret: Option<&'a str>,
+    pub nonce: Option<&'a str>,
+    pub access_token: &'a str,
+    pub code: Option<&'a str>,
+}
+
+/// Left-most half of the hash of the value, as in `at_hash` and `c_hash` claims
+fn half_hash(algorithm: Algorithm, value: &str) -> String {
+    let digest = match algorithm {
+        Algorithm::HS384 | Algorithm::RS384 | Algorithm::PS384 | Algorithm::ES384 => {
+            Sha384::digest(value.as_bytes()).to_vec()
+        }
+        Algorithm::HS512 | Algorithm::RS512 | Algorithm::PS512 | Algorithm::EdDSA => {
+            Sha512::digest(value.as_bytes()).to_vec()
+        }
+        _ => Sha256::digest(value.as_bytes()).to_vec(),
+    };
+
+    BASE64_URL_SAFE_NO_PAD.encode(&digest[..digest.len() / 2])
+}
+
+fn assert_hash(
+    claims: &Claims,
+    claim: &'static str,
+    algorithm: Algorithm,
+    value: Option<&str>,
+) -> Result<(), IdTokenError> {
+    match (claims.get(claim).and_then(|hash| hash.as_str()), value) {
+        (Some(hash), Some(value)) if hash != half_hash(algorithm, value) => {
+            Err(IdTokenError::ClaimMismatch(claim))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl IdTokenValidation<'_> {
+    /// Client secret verifying HMAC signed tokens
+    fn hmac_secret(&self, algorithm: Algorithm) -> Option<&str> {
+        match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => self.client_secret,
+            _ => None,
+        }
+    }
+
+    async fn decoding_key(
+        &self,
+        algorithm: Algorithm,
+        kid: Option<&str>,
+        refetch: bool,
+    ) -> Result<DecodingKey> {
+        if let Some(secret) = self.hmac_secret(algorithm) {
+            return Ok(DecodingKey::from_secret(secret.as_bytes()));
+        }
+
+        let jwks = JwksCache::new(self.jwks_url);
+        let key = match refetch {
+            true => jwks.fetch_key(kid).await?,
+            false => jwks.find_key(kid).await?,
+        }
+        .ok_or_else(|| IdTokenError::KeyNotFound(kid.unwrap_or_default().to_owned()))?;
+
+        Ok(DecodingKey::from_jwk(&key).map_err(IdTokenError::from)?)
+    }
+
+    pub async fn validate(&self, id_token: &str) -> Result<Claims> {
+        log::debug!("Validating ID token...");
+
+        let header = jsonwebtoken::decode_header(id_token).map_err(IdTokenError::from)?;
+        let key = self
+            .decoding_key(header.alg, header.kid.as_deref(), false)
+            .await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[self.issuer]);
+        validation.set_audience(&[self.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = match jsonwebtoken::decode::<Claims>(id_token, &key, &validation) {
+            // NOTE: The cached key may be outdated, e.g. when the provider rotated keys
+            // and the token has no `kid` to notice it
+            Err(e)
+                if *e.kind() == ErrorKind::InvalidSignature
+                    && self.hmac_secret(header.alg).is_none() =>
+            {
+                log::debug!(
+                    "ID token signature doesn't match the cached key. Fetching JWKS again..."
+                );
+                let key = self
+                    .decoding_key(header.alg, header.kid.as_deref(), true)
+                    .await?;
+                jsonwebtoken::decode::<Claims>(id_token, &key, &validation)
+            }
+            result => result,
+        }
+        .map_err(IdTokenError::from)?
+        .claims;
+
+        let issued_at = claims
+            .get("iat")
+            .and_then(|iat| iat.as_u64())
+            .ok_or(IdTokenError::MissingClaim("iat"))?;
+
+        if issued_at > jwt::now() + validation.leeway {
+            return Err(IdTokenError::ClaimMismatch("iat").into());
+        }
+
+        // NOTE: With multiple audiences the token has to be issued to this client
+        if claims.get("aud").is_some_and(|aud| aud.is_array())
+            && claims
+                .get("azp")
+                .is_some_and(|azp| azp.as_str() != Some(self.client_id))
+        {
+            return Err(IdTokenError::ClaimMismatch("azp").into());
+        }
+
+        if let Some(nonce) = self.nonce {
+            match claims.get("nonce").and_then(|value| value.as_str()) {
+                Some(value) if value == nonce => {}
+                Some(_) => return Err(IdTokenError::ClaimMismatch("nonce").into()),
+                None => return Err(IdTokenError::MissingClaim("nonce").into()),
+            }
+        }
+
+        assert_hash(&claims, "at_hash", header.alg, Some(self.access_token))?;
+        assert_hash(&claims, "c_hash", header.alg, self.code)?;
+
+        log::debug!("ID token is valid");
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use crate::file_cache::cache_file_path;
+    use crate::signing_algorithm::SigningAlgorithm;
+    use crate::test_server::{self, test_data};
+    use jsonwebtoken::EncodingKey;
+    use std::fs;
+
+    use super::*;
+
+    const CLIENT_SECRET: &str = "test-client-secret-of-at-least-32-bytes";
+
+    fn get_validation(jwks_url: &str) -> IdTokenValidation<'_> {
+        IdTokenValidation {
+            issuer: "https://my-idp.com",
+            jwks_url,
+            client_id: "test-client-id",
+            client_secret: Some(CLIENT_SECRET),
+            nonce: Some("test-nonce"),
+            access_token: "test-access-token",
+            code: None,
+        }
+    }
+
+    fn get_claims() -> Claims {
+        let now = jwt::now();
+
+        serde_json::from_value(serde_json::json!({
+            "iss": "https://my-idp.com",
+            "sub": "test-user",
+            "aud": "test-client-id",
+            "iat": now,
+            "exp": now + 300,
+            "nonce": "test-nonce",
+        }))
+        .unwrap()
+    }
+
+    fn sign_hs256(claims: &Claims, secret: &str) -> String {
+        jwt::sign(
+            &SigningAlgorithm::Hs256,
+            &EncodingKey::from_secret(secret.as_bytes()),
+            None,
+            claims,
+        )
+        .unwrap()
+    }
+
+    async fn validate_hs256(claims: Claims) -> Result<Claims> {
+        get_validation("https://my-idp.com/jwks")
+            .validate(&sign_hs256(&claims, CLIENT_SECRET))
+            .await
+    }
+
+    fn with_claim(name: &str, value: serde_json::Value) -> Claims {
+        let mut claims = get_claims();
+        claims.insert(name.to_owned(), value);
+        claims
+    }
+
+    fn error_of(result: Result<Claims>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[tokio::test]
+    async fn it_accepts_valid_id_token() {
+        let claims = validate_hs256(get_claims()).await.unwrap();
+
+        assert_eq!(claims["sub"], "test-user");
+    }
+
+    #[tokio::test]
+    async fn it_rejects_id_token_with_invalid_signature() {
+        let id_token = sign_hs256(&get_claims(), "other-client-secret-of-at-least-32-bytes");
+
+        let result = get_validation("https://my-idp.com/jwks")
+            .validate(&id_token)
+            .await;
+
+        assert!(error_of(result).contains("InvalidSignature"));
+    }
+
+    #[tokio::test]
+    async fn it_rejects_id_token_of_other_issuer() {
+        let result = validate_hs256(with_claim("iss", "https://other-idp.com".into())).await;
+
+        assert!(error_of(result).contains("InvalidIssuer"));
+    }
+
+    #[tokio::test]
+    async fn it_rejects_id_token_of_other_audience() {
+        let result = validate_hs256(with_claim("aud", "other-client-id".into())).await;
+
+        assert!(error_of(result).contains("InvalidAudience"));
+    }
+
+    #[tokio::test]
+    async fn it_checks_authorized_party_of_multiple_audiences() {
+        let mut claims = with_claim("aud", serde_json::json!(["test-client-id", "other"]));
+        claims.insert("azp".to_owned(), "other".into());
+        let mismatch = validate_hs256(claims.to_owned()).await;
+        claims.insert("azp".to_owned(), "test-client-id".into());
+        let valid = validate_hs256(claims).await;
+
+        assert_eq!(error_of(mismatch), "ID token `azp` claim doesn't match");
+        assert!(valid.is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_rejects_expired_id_token() {
+        let result = validate_hs256(with_claim("exp", (jwt::now() - 3600).into())).await;
+
+        assert!(error_of(result).contains("ExpiredSignature"));
+    }
+
+    #[tokio::test]
+    async fn it_checks_nonce_of_id_token() {
+        let mismatch = validate_hs256(with_claim("nonce", "other-nonce".into())).await;
+        let mut claims = get_claims();
+        claims.remove("nonce");
+        let missing = validate_hs256(claims).await;
+
+        assert_eq!(error_of(mismatch), "ID token `nonce` claim doesn't match");
+        assert_eq!(error_of(missing), "ID token is missing `nonce` claim");
+    }
+
+    /// Validates an ES256 signed ID token, while the JWKS cache holds an outdated key and the
+    /// JWKS url serves the given file. Returns the result, the requests and the cached JWKS
+    async fn validate_with_outdated_jwks_cache(
+        served_jwks: &str,
+    ) -> (Result<Claims>, Vec<String>, String) {
+        let jwks = fs::read_to_string(test_data(served_jwks)).unwrap();
+        let (url, requests) = test_server::serve(vec![(200, jwks)]).await;
+        let jwks_url = format!("{url}/jwks");
+        let cache_path = cache_file_path("jwks", &jwks_url);
+        fs::create_dir_all(cache_path.parent().unwrap()).unwrap();
+        fs::copy(test_data("ec_stale_jwks.json"), &cache_path).unwrap();
+        let key =
+            jwt::read_encoding_key(&SigningAlgorithm::Es256, &test_data("ec_private_key.pem"))
+                .unwrap();
+        let id_token = jwt::sign(
+            &SigningAlgorithm::Es256,
+            &key,
+            Some("test-key-id"),
+            &get_claims(),
+        )
+        .unwrap();
+
+        let result = get_validation(&jwks_url).validate(&id_token).await;
+        let requests = requests.await.unwrap();
+        let cached_jwks = fs::read_to_string(&cache_path).unwrap();
+        fs::remove_file(&cache_path).unwrap();
+
+        (result, requests, cached_jwks)
+    }
+
+    #[tokio::test]
+    async fn it_fetches_jwks_once_again_when_cached_key_is_outdated() {
+        let (result, requests, cached_jwks) =
+            validate_with_outdated_jwks_cache("ec_jwks.json").await;
+
+        assert!(result.is_ok());
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("GET /jwks "));
+        assert_eq!(
+            cached_jwks,
+            fs::read_to_string(test_data("ec_jwks.json")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_jwks_only_once_for_invalid_signature() {
+        let (result, requests, _) = validate_with_outdated_jwks_cache("ec_stale_jwks.json").await;
+
+        assert!(error_of(result).contains("InvalidSignature"));
+        assert_eq!(requests.len(), 1);
+    }
+}
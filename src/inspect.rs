@@ -0,0 +1,115 @@
+use crate::jwks::JwksCache;
+use crate::jwt::{self, Claims};
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use jsonwebtoken::Validation;
+use serde_json::Value;
+use std::fmt;
+
+const TIME_CLAIMS: [&str; 3] = ["exp", "iat", "nbf"];
+
+/// Header and claims of a JWT decoded without checking the signature. More: <https://www.rfc-editor.org/rfc/rfc7519#section-7.2>
+pub struct TokenInspection {
+    header: Claims,
+    claims: Claims,
+    signature: Option<String>,
+}
+
+fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds}s"),
+        _ => format!("{hours}h {minutes}m {seconds}s"),
+    }
+}
+
+fn format_time(claim: &str, timestamp: u64) -> String {
+    let time = DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_else(|| timestamp.to_string());
+    let now = jwt::now();
+
+    let relative = match (claim, timestamp > now) {
+        ("exp", true) => format!("expires in {}", format_duration(timestamp - now)),
+        ("exp", false) => format!("expired {} ago", format_duration(now - timestamp)),
+        (_, true) => format!("in {}", format_duration(timestamp - now)),
+        (_, false) => format!("{} ago", format_duration(now - timestamp)),
+    };
+
+    format!("{time} ({relative})")
+}
+
+impl TokenInspection {
+    /// Returns `None` for opaque and encrypted tokens, which can't be read locally
+    pub fn decode(token: &str) -> Option<TokenInspection> {
+        let [header, claims, _] = token.trim().split('.').collect::<Vec<_>>()[..] else {
+            return None;
+        };
+
+        Some(TokenInspection {
+            header: jwt::decode_part(header)?,
+            claims: jwt::decode_part(claims)?,
+            signature: None,
+        })
+    }
+
+    /// Checks the signature with the provider's signing keys
+    pub async fn verify(
+        &mut self,
+        token: &str,
+        jwks_url: &str,
+        client_secret: Option<&str>,
+    ) -> Result<()> {
+        let header = jsonwebtoken::decode_header(token).context("Token header is malformed")?;
+
+        let key = JwksCache::new(jwks_url)
+            .decoding_key(header.alg, header.kid.as_deref(), client_secret)
+            .await?;
+
+        // NOTE: Only the signature is checked. Times are reported next to the claims
+        let mut validation = Validation::new(header.alg);
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        validation.required_spec_claims.clear();
+
+        self.signature = Some(
+            match jsonwebtoken::decode::<Claims>(token.trim(), &key, &validation) {
+                Ok(_) => "valid".to_owned(),
+                Err(e) => format!("invalid ({e})"),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for TokenInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = serde_json::to_string_pretty(&self.header).map_err(|_| fmt::Error)?;
+        let claims = serde_json::to_string_pretty(&self.claims).map_err(|_| fmt::Error)?;
+
+        writeln!(f, "Header:\n{header}\n")?;
+        write!(f, "Claims:\n{claims}")?;
+
+        let times: Vec<String> = TIME_CLAIMS
+            .iter()
+            .filter_map(|claim| {
+                let timestamp = self.claims.get(*claim).and_then(Value::as_u64)?;
+
+                Some(format!("{claim}: {}", format_time(claim, timestamp)))
+            })
+            .collect();
+
+        if !times.is_empty() {
+            write!(f, "\n\n{}", times.join("\n"))?;
+        }
+
+        if let Some(signature) = &self.signature {
+            write!(f, "\n\nSignature: {signature}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::file_cache::cache_file_path;
+use anyhow::{Context, Result};
+use jsonwebtoken::jwk::{Jwk, JwkSet, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey};
+use std::path::PathBuf;
+use tokio::fs;
+
+/// Provider's signing keys cached in ~/.doken/cache directory. More: <https://www.rfc-editor.org/rfc/rfc7517#section-5>
+pub struct JwksCache {
+    jwks_url: String,
+    file_path: PathBuf,
+}
+
+impl JwksCache {
+    pub fn new(jwks_url: &str) -> JwksCache {
+        JwksCache {
+            jwks_url: jwks_url.to_owned(),
+            file_path: cache_file_path("jwks", jwks_url),
+        }
+    }
+
+    async fn read(&self) -> Option<JwkSet> {
+        let content = fs::read_to_string(&self.file_path).await.ok()?;
+
+        serde_json::from_str::<JwkSet>(&content).ok()
+    }
+
+    async fn fetch(&self) -> Result<JwkSet> {
+        log::debug!("Fetching JWKS from {}", self.jwks_url);
+
+        let content = reqwest::get(self.jwks_url.to_owned())
+            .await
+            .context("Couldn't reach out to the JWKS url")?
+            .error_for_status()
+            .context("Failed during JWKS call")?
+            .text()
+            .await?;
+
+        let jwks = serde_json::from_str::<JwkSet>(&content)
+            .context("Couldn't process json given by the JWKS url")?;
+
+        if let Some(cache_dir) = self.file_path.parent() {
+            fs::create_dir_all(cache_dir).await?;
+        }
+        fs::write(&self.file_path, content)
+            .await
+            .context("Failed to write JWKS cache")?;
+
+        Ok(jwks)
+    }
+
+    fn find(jwks: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+        match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None => jwks
+                .keys
+                .iter()
+                .find(|key| key.common.public_key_use != Some(PublicKeyUse::Encryption))
+                .cloned(),
+        }
+    }
+
+    /// Looks up the key in the cache first. Keys are fetched again when it's missing, because
+    /// providers rotate them.
+    pub async fn find_key(&self, kid: Option<&str>) -> Result<Option<Jwk>> {
+        if let Some(key) = self.read().await.and_then(|jwks| Self::find(&jwks, kid)) {
+            log::debug!("Signing key found in JWKS cache");
+            return Ok(Some(key));
+        }
+
+        self.fetch_key(kid).await
+    }
+
+    /// Looks up the key in freshly fetched keys, skipping the cache
+    pub async fn fetch_key(&self, kid: Option<&str>) -> Result<Option<Jwk>> {
+        Ok(Self::find(&self.fetch().await?, kid))
+    }
+
+    /// Key verifying the signature of a JWT. HMAC signed ones are verified with the client secret
+    pub async fn decoding_key(
+        &self,
+        algorithm: Algorithm,
+        kid: Option<&str>,
+        client_secret: Option<&str>,
+    ) -> Result<DecodingKey> {
+        if let (Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512, Some(secret)) =
+            (algorithm, client_secret)
+        {
+            return Ok(DecodingKey::from_secret(secret.as_bytes()));
+        }
+
+        let jwk = self.find_key(kid).await?.with_context(|| {
+            format!(
+                "Signing key `{}` cannot be found in JWKS",
+                kid.unwrap_or_default()
+            )
+        })?;
+
+        DecodingKey::from_jwk(&jwk).context("Signing key given by JWKS is malformed")
+    }
+}
@@ -0,0 +1,92 @@
+use crate::signing_algorithm::SigningAlgorithm;
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rand::distr::{Alphanumeric, SampleString};
+use serde_json::{Map, Value};
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub type Claims = Map<String, Value>;
+
+impl SigningAlgorithm {
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            SigningAlgorithm::Rs256 => Algorithm::RS256,
+            SigningAlgorithm::Es256 => Algorithm::ES256,
+            SigningAlgorithm::Ps256 => Algorithm::PS256,
+            SigningAlgorithm::Hs256 => Algorithm::HS256,
+        }
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Decodes a base64url encoded JSON part of a JWT without checking the signature
+pub fn decode_part(part: &str) -> Option<Claims> {
+    let json = BASE64_URL_SAFE_NO_PAD.decode(part).ok()?;
+
+    serde_json::from_slice::<Claims>(&json).ok()
+}
+
+/// Time given by `exp` claim. `None` for opaque and encrypted tokens, which can't be read locally.
+/// More: <https://www.rfc-editor.org/rfc/rfc7519#section-4.1.4>
+pub fn expires(token: &str) -> Option<SystemTime> {
+    let [_, claims, _] = token.trim().split('.').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let exp = decode_part(claims)?.get("exp").and_then(Value::as_u64)?;
+
+    Some(UNIX_EPOCH + Duration::from_secs(exp))
+}
+
+/// Random `jti` claim making every JWT unique
+pub fn jti() -> String {
+    Alphanumeric.sample_string(&mut rand::rng(), 32)
+}
+
+/// Reads a PEM private key or, for HS256, a file containing the shared secret
+pub fn read_encoding_key(algorithm: &SigningAlgorithm, path: &str) -> Result<EncodingKey> {
+    let key = fs::read(path).with_context(|| format!("Cannot read the key file {path}"))?;
+
+    let key = match algorithm {
+        SigningAlgorithm::Rs256 | SigningAlgorithm::Ps256 => EncodingKey::from_rsa_pem(&key),
+        SigningAlgorithm::Es256 => EncodingKey::from_ec_pem(&key),
+        SigningAlgorithm::Hs256 => Ok(EncodingKey::from_secret(key.trim_ascii_end())),
+    };
+
+    key.with_context(|| format!("The key file {path} doesn't contain a correct {algorithm:?} key"))
+}
+
+/// Claims required by JWT assertions. More: <https://www.rfc-editor.org/rfc/rfc7523#section-3>
+pub fn assertion_claims(issuer: &str, subject: &str, audience: &str, lifetime: u64) -> Claims {
+    let issued_at = now();
+    let mut claims = Claims::new();
+
+    claims.insert("iss".to_owned(), issuer.into());
+    claims.insert("sub".to_owned(), subject.into());
+    claims.insert("aud".to_owned(), audience.into());
+    claims.insert("iat".to_owned(), issued_at.into());
+    claims.insert("exp".to_owned(), (issued_at + lifetime).into());
+    claims.insert("jti".to_owned(), jti().into());
+
+    claims
+}
+
+pub fn sign(
+    algorithm: &SigningAlgorithm,
+    key: &EncodingKey,
+    key_id: Option<&str>,
+    claims: &Claims,
+) -> Result<String> {
+    let mut header = Header::new(algorithm.algorithm());
+    header.kid = key_id.map(|kid| kid.to_owned());
+
+    jsonwebtoken::encode(&header, claims, key).context("Failed to sign a JWT")
+}
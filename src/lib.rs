@@ -1,83 +1,404 @@
 #![deny(warnings)]
 
-use crate::args::Arguments;
+use crate::args::{Args, Arguments};
+use crate::auth_browser::authorization_page::AuthorizationPage;
+use crate::auth_browser::loopback_server::LoopbackServer;
+use crate::auth_browser::manual_page::ManualPage;
+use crate::dpop::DPoPKey;
 use crate::file_state::FileState;
 use crate::grant::Grant;
+use crate::inspect::TokenInspection;
 use crate::oauth_client::OAuthClient;
+use crate::output::Output;
 use crate::retrievers::authorization_code_retriever::AuthorizationCodeRetriever;
 use crate::retrievers::authorization_code_with_pkce_retriever::AuthorizationCodeWithPKCERetriever;
 use crate::retrievers::client_credentials_retriever::ClientCredentialsRetriever;
+use crate::retrievers::device_code_retriever::DeviceCodeRetriever;
 use crate::retrievers::file_retriever::FileRetriever;
 use crate::retrievers::implicit_retriever::ImplicitRetriever;
+use crate::retrievers::jwt_bearer_retriever::JwtBearerRetriever;
 use crate::retrievers::resource_owner_password_client_credentials_retriever::ResourceOwnerPasswordClientCredentialsRetriever;
+use crate::retrievers::token_exchange_retriever::TokenExchangeRetriever;
 use crate::retrievers::token_retriever::TokenRetriever;
+use crate::token_info::TokenInfo;
+use crate::token_key::{TokenKey, normalize_scope};
 use anyhow::Context;
 use anyhow::Result;
+use anyhow::bail;
 use auth_browser::browser::Browser;
+use oauth2::{AccessToken, RefreshToken, StandardRevocableToken};
+use std::io::Read;
 use tokio::sync::MutexGuard;
 
 pub mod args;
 pub mod auth_browser;
+pub mod client_auth_method;
+mod client_certificate;
 mod config_file;
+mod dpop;
+mod file_cache;
 mod file_state;
 pub mod grant;
+mod http_client;
+mod id_token;
+mod inspect;
+mod jwks;
+mod jwt;
 mod oauth_client;
 mod openidc_discovery;
+pub mod output;
 mod retrievers;
+pub mod signing_algorithm;
 mod token_info;
+mod token_key;
 
-pub async fn get_token(args: Arguments, auth_browser: MutexGuard<'_, Browser>) -> Result<String> {
-    let oauth_client = OAuthClient::new(&args).await?;
-    let mut file_state = FileState::new()?;
+#[cfg(test)]
+mod test_server;
 
-    if !args.force {
-        let mut file_retriever = FileRetriever::new(&args, &oauth_client, &mut file_state);
+async fn get_subject_token(args: &Arguments, file_state: &mut FileState) -> Result<String> {
+    let profile = match &args.subject_token_profile {
+        Some(profile) => profile,
+        None => {
+            return args
+                .subject_token
+                .to_owned()
+                .context("Subject token has to be provided for `token-exchange` grant");
+        }
+    };
 
-        let file_token_info = file_retriever.retrieve().await;
+    // NOTE: The token is looked up the same way as by the profile's own run, so it's refreshed or
+    // narrowed down from a covering one too
+    let profile_args = Args::parse_profile(profile).await?;
+    let token_info = retrieve_cached_token_info(&profile_args, file_state)
+        .await
+        .with_context(|| {
+            format!(
+                "No valid token cached for `{profile}` profile. Please run `doken --profile {profile}` first"
+            )
+        })?;
 
-        if let Ok(file_token_info) = file_token_info {
-            return Ok(file_token_info.access_token);
-        }
+    Ok(token_info.access_token)
+}
+
+fn get_dpop_key(args: &Arguments, file_state: &mut FileState) -> Result<Option<DPoPKey>> {
+    if !args.dpop {
+        return Ok(None);
+    }
+
+    Ok(Some(DPoPKey::from_state(file_state)?))
+}
+
+/// Token cached for the arguments. Expiring ones are refreshed and missing ones are narrowed down
+/// from a token covering their scope
+async fn retrieve_cached_token_info(
+    args: &Arguments,
+    file_state: &mut FileState,
+) -> Result<TokenInfo> {
+    let dpop_key = get_dpop_key(args, file_state)?;
+    let oauth_client = OAuthClient::new(args, dpop_key)?;
+
+    FileRetriever::new(args, &oauth_client, file_state)
+        .retrieve()
+        .await
+}
+
+/// Scope requested by a new grant. Scopes granted to the client's other cached tokens are requested
+/// too, so the cached grant grows over time and covers later requests
+fn grant_scope(args: &Arguments, file_state: &mut FileState) -> String {
+    let sibling_scopes = file_state
+        .read_sibling_token_info(&TokenKey::from(args))
+        .into_iter()
+        .map(|(key, token_info)| token_info.granted_scope(&key).to_owned());
+
+    normalize_scope(
+        &std::iter::once(args.scope.to_owned())
+            .chain(sibling_scopes)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// Loopback server of `--system-browser`. It's bound before the client is created, as the redirect
+/// uri has to contain the port it listens on
+async fn bind_loopback_server(args: &Arguments) -> Result<Option<LoopbackServer>> {
+    let uses_browser = matches!(
+        args.grant,
+        Grant::AuthorizationCodeWithPkce | Grant::AuthorizationCode | Grant::Implicit
+    );
+
+    match (&args.callback_url, args.system_browser && uses_browser) {
+        (Some(callback_url), true) => Ok(Some(LoopbackServer::bind(callback_url).await?)),
+        _ => Ok(None),
+    }
+}
+
+async fn open_authorization_page(
+    args: &Arguments,
+    auth_browser: MutexGuard<'_, Browser>,
+    loopback_server: Option<LoopbackServer>,
+) -> Result<Box<dyn AuthorizationPage>> {
+    match loopback_server {
+        Some(loopback_server) => Ok(Box::new(loopback_server)),
+        None if args.no_browser => Ok(Box::new(ManualPage::new(
+            matches!(args.grant, Grant::AuthorizationCodeWithPkce),
+            args.no_browser_timeout,
+        ))),
+        None => Ok(Box::new(auth_browser.open_page().await?)),
     }
+}
+
+async fn get_token_info(
+    args: &Arguments,
+    auth_browser: MutexGuard<'_, Browser>,
+) -> Result<TokenInfo> {
+    let mut file_state = FileState::new()?;
+
+    if !args.force
+        && let Ok(file_token_info) = retrieve_cached_token_info(args, &mut file_state).await
+    {
+        return Ok(file_token_info);
+    }
+
+    let loopback_server = bind_loopback_server(args).await?;
+    let grant_args = Arguments {
+        scope: grant_scope(args, &mut file_state),
+        callback_url: match &loopback_server {
+            Some(loopback_server) => Some(loopback_server.callback_url().to_string()),
+            None => args.callback_url.to_owned(),
+        },
+        ..args.clone()
+    };
+    let dpop_key = get_dpop_key(args, &mut file_state)?;
+    let oauth_client = OAuthClient::new(&grant_args, dpop_key)?;
+    oauth_client.assert_supports_grant().await?;
 
     let mut retriever: Box<dyn TokenRetriever> = match args.grant {
         Grant::AuthorizationCodeWithPkce => {
-            let auth_page = auth_browser.open_page().await?;
-            drop(auth_browser);
+            let auth_page = open_authorization_page(args, auth_browser, loopback_server).await?;
             Box::new(AuthorizationCodeWithPKCERetriever::new(
-                &args,
+                &grant_args,
                 &oauth_client,
                 auth_page,
             ))
         }
         Grant::AuthorizationCode => {
-            let auth_page = auth_browser.open_page().await?;
-            drop(auth_browser);
+            let auth_page = open_authorization_page(args, auth_browser, loopback_server).await?;
             Box::new(AuthorizationCodeRetriever::new(
-                &args,
+                &grant_args,
                 &oauth_client,
                 auth_page,
             ))
         }
         Grant::Implicit => {
-            let auth_page = auth_browser.open_page().await?;
-            drop(auth_browser);
-            Box::new(ImplicitRetriever::new(&args, &oauth_client, auth_page))
+            let auth_page = open_authorization_page(args, auth_browser, loopback_server).await?;
+            Box::new(ImplicitRetriever::new(
+                &grant_args,
+                &oauth_client,
+                auth_page,
+            ))
         }
         Grant::ResourceOwnerPasswordClientCredentials => Box::new(
             ResourceOwnerPasswordClientCredentialsRetriever::new(&oauth_client),
         ),
         Grant::ClientCredentials => Box::new(ClientCredentialsRetriever::new(&oauth_client)),
+        Grant::DeviceCode => Box::new(DeviceCodeRetriever::new(&oauth_client)),
+        Grant::TokenExchange => {
+            let subject_token = get_subject_token(args, &mut file_state).await?;
+            Box::new(TokenExchangeRetriever::new(&oauth_client, subject_token))
+        }
+        Grant::JwtBearer => Box::new(JwtBearerRetriever::new(&oauth_client)),
     };
 
-    let token_info = retriever
+    let mut token_info = retriever
         .retrieve()
         .await
-        .context("Failed to retrieve a token")?;
+        .context("Failed to retrieve a token")?
+        .with_default_lifetime(args.default_token_lifetime);
+    token_info.cert_thumbprint = oauth_client.certificate_thumbprint();
+    token_info.scope = token_info.scope.or(Some(grant_args.scope.to_owned()));
 
     file_state
-        .upsert_token_info(args.client_id.to_owned(), token_info.to_owned())
-        .unwrap();
+        .upsert_token_info(&TokenKey::from(args), token_info.to_owned())
+        .context("Failed to save the token to the state")?;
 
-    Ok(token_info.access_token)
+    Ok(token_info)
+}
+
+fn select_token(token_info: TokenInfo, output: &Output) -> Result<String> {
+    match output {
+        Output::AccessToken => Ok(token_info.access_token),
+        Output::IdToken => token_info
+            .id_token
+            .context("ID token wasn't returned by the IdP. Please add `openid` to `--scope`"),
+        Output::RefreshToken => token_info.refresh_token.context(
+            "Refresh token wasn't returned by the IdP. Please add `offline_access` to `--scope`",
+        ),
+    }
+}
+
+pub async fn get_token(args: Arguments, auth_browser: MutexGuard<'_, Browser>) -> Result<String> {
+    let token_info = get_token_info(&args, auth_browser).await?;
+
+    select_token(token_info, &args.output)
+}
+
+pub async fn get_dpop_proof(
+    args: Arguments,
+    auth_browser: MutexGuard<'_, Browser>,
+    method: &str,
+    url: &str,
+    nonce: Option<&str>,
+) -> Result<String> {
+    let access_token = get_token_info(&args, auth_browser).await?.access_token;
+
+    let mut file_state = FileState::new()?;
+    let dpop_key = DPoPKey::from_state(&mut file_state)?;
+
+    dpop_key.proof(method, url, nonce, Some(&access_token))
+}
+
+pub async fn get_userinfo(
+    args: Arguments,
+    auth_browser: MutexGuard<'_, Browser>,
+) -> Result<String> {
+    let access_token = get_token_info(&args, auth_browser).await?.access_token;
+
+    let mut file_state = FileState::new()?;
+    let dpop_key = get_dpop_key(&args, &mut file_state)?;
+    let oauth_client = OAuthClient::new(&args, dpop_key)?;
+
+    let claims = oauth_client.userinfo(&access_token).await?;
+
+    serde_json::to_string_pretty(&claims).context("Failed to print the UserInfo response")
+}
+
+async fn revoke_token_info(oauth_client: &OAuthClient<'_>, token_info: TokenInfo) -> Result<()> {
+    // NOTE: Revoking the refresh token first, as the IdP may revoke access tokens issued with it
+    if let Some(refresh_token) = token_info.refresh_token {
+        oauth_client
+            .revoke_token(StandardRevocableToken::RefreshToken(RefreshToken::new(
+                refresh_token,
+            )))
+            .await?;
+    }
+
+    oauth_client
+        .revoke_token(StandardRevocableToken::AccessToken(AccessToken::new(
+            token_info.access_token,
+        )))
+        .await
+}
+
+pub async fn revoke(args: Arguments, all: bool) -> Result<String> {
+    let mut file_state = FileState::new()?;
+    let dpop_key = get_dpop_key(&args, &mut file_state)?;
+    let oauth_client = OAuthClient::new(&args, dpop_key)?;
+    let client = TokenKey::from(&args);
+
+    // NOTE: IdPs revoke only tokens issued to the client authenticating the request, so tokens
+    // of other clients are left for their own arguments. More: <https://www.rfc-editor.org/rfc/rfc7009#section-2.1>
+    let (entries, skipped): (Vec<_>, Vec<_>) = if all {
+        file_state
+            .read_all_token_info()
+            .into_iter()
+            .partition(|(key, _)| key.is_of_client(&client))
+    } else {
+        let entries = file_state
+            .read_token_info(&client)
+            .map(|token_info| vec![(client.to_owned(), token_info)])
+            .unwrap_or_default();
+
+        (entries, vec![])
+    };
+
+    for (key, _) in &skipped {
+        log::debug!("Skipping token of other client: {key}");
+    }
+
+    if entries.is_empty() {
+        bail!("No token cached for the client. Nothing to revoke");
+    }
+
+    let total = entries.len();
+    let mut failures = vec![];
+
+    for (key, token_info) in entries {
+        match revoke_token_info(&oauth_client, token_info).await {
+            Ok(()) => file_state.clear_token_info(&key)?,
+            Err(e) => failures.push(format!("{key}: {e:#}")),
+        }
+    }
+
+    let revoked = total - failures.len();
+
+    if !failures.is_empty() {
+        bail!(
+            "Revoked {revoked} of {total} cached token(s). Failed to revoke:\n{}",
+            failures.join("\n")
+        );
+    }
+
+    match skipped.len() {
+        0 => Ok(format!("Revoked {revoked} cached token(s)")),
+        skipped => Ok(format!(
+            "Revoked {revoked} cached token(s). Skipped {skipped} token(s) of other clients"
+        )),
+    }
+}
+
+pub async fn introspect(args: Arguments, token: Option<String>) -> Result<String> {
+    let mut file_state = FileState::new()?;
+
+    // NOTE: The cached token is introspected as is, without refreshing it first
+    let token = match token {
+        Some(token) => token,
+        None => {
+            file_state
+                .read_token_info(&TokenKey::from(&args))
+                .context("No token cached for the arguments. Please run doken with them first or provide `--token`")?
+                .access_token
+        }
+    };
+
+    let dpop_key = get_dpop_key(&args, &mut file_state)?;
+    let oauth_client = OAuthClient::new(&args, dpop_key)?;
+
+    let response = oauth_client.introspect_token(token).await?;
+
+    serde_json::to_string_pretty(&response).context("Failed to print the introspection response")
+}
+
+pub async fn inspect(args: Arguments, token_stdin: bool, verify: bool) -> Result<String> {
+    let token = if token_stdin {
+        let mut token = String::new();
+        std::io::stdin().read_to_string(&mut token)?;
+        token.trim().to_owned()
+    } else {
+        // NOTE: The token is looked up the same way as while printing it, so a token covering
+        // the requested scope is found too
+        let token_info = retrieve_cached_token_info(&args, &mut FileState::new()?)
+            .await
+            .context("No token cached for the arguments. Please run doken with them first or use `--token-stdin`")?;
+
+        select_token(token_info, &args.output)?
+    };
+
+    let Some(mut inspection) = TokenInspection::decode(&token) else {
+        return Ok(
+            "The token is opaque, so it cannot be decoded locally. Please use `introspect` command to ask the IdP about it".to_owned(),
+        );
+    };
+
+    if verify {
+        let oauth_client = OAuthClient::new(&args, None)?;
+        let jwks_url = oauth_client.jwks_url().await?.context(
+            "`--jwks-url` or `--discovery-url` has to be provided to verify the signature",
+        )?;
+
+        inspection
+            .verify(&token, jwks_url, args.client_secret.as_deref())
+            .await?;
+    }
+
+    Ok(inspection.to_string())
 }
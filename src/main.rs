@@ -1,13 +1,18 @@
 #![deny(warnings)]
 
 use anyhow::Result;
-use doken::args::Args;
-use doken::auth_browser::browser::Browser;
-use doken::get_token;
+use doken::args::{Args, Operation};
+use doken::auth_browser::browser::{Browser, LaunchOptions};
+use doken::auth_browser::callback::RequestError;
+use doken::{get_dpop_proof, get_token, get_userinfo, inspect, introspect, revoke};
 use std::env;
 use std::process::exit;
 use tokio::sync::Mutex;
 
+/// Exit code of the authorization aborted by closing the browser. 1 is taken by other errors and 2
+/// by invalid arguments
+const BROWSER_CLOSED_EXIT_CODE: i32 = 3;
+
 fn enable_debug_via_args() {
     let has_debug_flag = env::args().any(|s| s.eq("--debug") || s.eq("-d"));
 
@@ -16,6 +21,13 @@ fn enable_debug_via_args() {
     }
 }
 
+fn is_browser_closed(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<RequestError>(),
+        Some(RequestError::BrowserClosed)
+    )
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     enable_debug_via_args();
@@ -23,9 +35,32 @@ async fn main() -> Result<()> {
 
     let args = Args::parse().await;
 
-    {
-        let auth_browser = Mutex::new(Browser::new(false));
-        println!("{}", get_token(args, auth_browser.lock().await).await?);
+    let result = {
+        let auth_browser = Mutex::new(Browser::with_options(false, LaunchOptions::from(&args)));
+        let auth_browser = auth_browser.lock().await;
+
+        match args.operation.to_owned() {
+            Some(Operation::DpopProof { method, url, nonce }) => {
+                get_dpop_proof(args, auth_browser, &method, &url, nonce.as_deref()).await
+            }
+            Some(Operation::Revoke { all }) => revoke(args, all).await,
+            Some(Operation::Userinfo) => get_userinfo(args, auth_browser).await,
+            Some(Operation::Inspect {
+                token_stdin,
+                verify,
+            }) => inspect(args, token_stdin, verify).await,
+            Some(Operation::Introspect { token }) => introspect(args, token).await,
+            None => get_token(args, auth_browser).await,
+        }
+    };
+
+    match result {
+        Ok(output) => println!("{output}"),
+        Err(e) if is_browser_closed(&e) => {
+            eprintln!("Error: {e:?}");
+            exit(BROWSER_CLOSED_EXIT_CODE);
+        }
+        Err(e) => return Err(e),
     }
     exit(0);
 }
@@ -1,29 +1,96 @@
 use crate::args::Arguments;
-use crate::openidc_discovery::get_endpoints_from_discovery_url;
-use anyhow::{Context, Result};
-use oauth2::basic::{
-    BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
-    BasicTokenResponse,
+use crate::client_auth_method::ClientAuthMethod;
+use crate::client_certificate::ClientCertificate;
+use crate::dpop::DPoPKey;
+use crate::grant::{
+    DEVICE_CODE_GRANT_TYPE, Grant, JWT_BEARER_GRANT_TYPE, TOKEN_EXCHANGE_GRANT_TYPE,
 };
+use crate::http_client::HttpClient;
+use crate::id_token::{IdTokenFields, IdTokenValidation};
+use crate::jwks::JwksCache;
+use crate::jwt::{self, Claims};
+use crate::openidc_discovery::{
+    MtlsEndpointAliases, OpenIDProviderMetadata, get_metadata_from_discovery_url,
+};
+use crate::signing_algorithm::SigningAlgorithm;
+use anyhow::{Context, Result, bail};
+use jsonwebtoken::{EncodingKey, Validation};
+use oauth2::basic::{BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenType};
 use oauth2::{
-    AuthUrl, AuthorizationCode, AuthorizationRequest, Client, ClientId, ClientSecret, CsrfToken,
-    EndpointNotSet, EndpointSet, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RefreshToken,
-    ResourceOwnerPassword, ResourceOwnerUsername, Scope, StandardRevocableToken, TokenUrl,
+    AccessToken, AuthType, AuthUrl, AuthorizationCode, AuthorizationRequest, Client, ClientId,
+    ClientSecret, CsrfToken, DeviceAuthorizationUrl, DeviceCodeErrorResponse,
+    DeviceCodeErrorResponseType, EndpointMaybeSet, EndpointSet, ExtraTokenFields, IntrospectionUrl,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RefreshToken, ResourceOwnerPassword,
+    ResourceOwnerUsername, RevocationUrl, Scope, StandardDeviceAuthorizationResponse,
+    StandardRevocableToken, StandardTokenIntrospectionResponse, StandardTokenResponse,
+    TokenResponse, TokenUrl,
 };
 use rand::distr::{Alphanumeric, SampleString};
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use reqwest::redirect::Policy;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
 use url::Url;
 
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+const CLIENT_ASSERTION_LIFETIME: u64 = 60;
+const REQUEST_OBJECT_LIFETIME: u64 = 300;
+const JWT_CONTENT_TYPE: &str = "application/jwt";
+const OPENID_SCOPE: &str = "openid";
+const OFFLINE_ACCESS_SCOPE: &str = "offline_access";
+const SLOW_DOWN_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type DokenTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
+/// Members of the introspection response not defined by the RFC. More: <https://www.rfc-editor.org/rfc/rfc7662#section-2.2>
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct IntrospectionFields {
+    #[serde(flatten)]
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ExtraTokenFields for IntrospectionFields {}
+
+pub type DokenIntrospectionResponse =
+    StandardTokenIntrospectionResponse<IntrospectionFields, BasicTokenType>;
+
+/// Scope without `offline_access`, which makes no sense for grants without a refresh token
+fn without_offline_access(scope: &str) -> String {
+    scope
+        .split_whitespace()
+        .filter(|scope| *scope != OFFLINE_ACCESS_SCOPE)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the scope makes it an OpenID Connect request
+fn is_openid_scope(scope: &str) -> bool {
+    scope.split_whitespace().any(|scope| scope == OPENID_SCOPE)
+}
+
+/// Interval of the next device code poll, while the user hasn't completed the authorization yet.
+/// More: <https://www.rfc-editor.org/rfc/rfc8628#section-3.5>
+fn next_poll_interval(error: &DeviceCodeErrorResponse, interval: Duration) -> Result<Duration> {
+    match error.error() {
+        DeviceCodeErrorResponseType::AuthorizationPending => Ok(interval),
+        DeviceCodeErrorResponseType::SlowDown => Ok(interval + SLOW_DOWN_INTERVAL),
+        _ => bail!("The token endpoint responded with {error}"),
+    }
+}
+
 type BaseClient<
-    HasAuthUrl = EndpointSet,
-    HasDeviceAuthUrl = EndpointNotSet,
-    HasIntrospectionUrl = EndpointNotSet,
-    HasRevocationUrl = EndpointNotSet,
+    HasAuthUrl = EndpointMaybeSet,
+    HasDeviceAuthUrl = EndpointMaybeSet,
+    HasIntrospectionUrl = EndpointMaybeSet,
+    HasRevocationUrl = EndpointMaybeSet,
     HasTokenUrl = EndpointSet,
 > = Client<
     BasicErrorResponse,
-    BasicTokenResponse,
-    BasicTokenIntrospectionResponse,
+    DokenTokenResponse,
+    DokenIntrospectionResponse,
     StandardRevocableToken,
     BasicRevocationErrorResponse,
     HasAuthUrl,
@@ -34,14 +101,50 @@ type BaseClient<
 >;
 pub struct OAuthClient<'a> {
     args: &'a Arguments,
+    http: HttpClient,
+    certificate: Option<ClientCertificate>,
+    provider: OnceCell<Provider>,
+}
+
+/// Client configured with the provider's endpoints. Resolved on the first request to the provider,
+/// so tokens taken from the state never wait for the discovery
+struct Provider {
     inner: BaseClient,
-    http: reqwest::Client,
+    pushed_authorization_request_url: Option<Url>,
+    issuer: Option<String>,
+    jwks_url: Option<String>,
+    userinfo_url: Option<String>,
+    metadata: Option<OpenIDProviderMetadata>,
+}
+
+#[derive(Debug)]
+struct Endpoints {
+    issuer: Option<String>,
+    jwks_url: Option<String>,
+    userinfo_url: Option<String>,
+    token_url: Option<String>,
+    authorization_url: Option<String>,
+    device_authorization_url: Option<String>,
+    pushed_authorization_request_url: Option<String>,
+    revocation_url: Option<String>,
+    introspection_url: Option<String>,
+    require_pushed_authorization_requests: bool,
 }
+
+/// More: <https://www.rfc-editor.org/rfc/rfc9126#section-2.2>
+#[derive(Deserialize, Debug)]
+struct PushedAuthorizationResponse {
+    request_uri: String,
+}
+
 impl OAuthClient<'_> {
     fn get_client(
         args: &Arguments,
         token_url: Option<&str>,
-        authorization_url: &str,
+        authorization_url: Option<&str>,
+        device_authorization_url: Option<&str>,
+        revocation_url: Option<&str>,
+        introspection_url: Option<&str>,
     ) -> Result<BaseClient> {
         let token = match token_url {
             Some(url) => Some(TokenUrl::new(url.to_owned()).with_context(|| {
@@ -50,20 +153,63 @@ impl OAuthClient<'_> {
             None => None,
         };
 
-        let mut client: BaseClient = BaseClient::new(ClientId::new(args.client_id.to_owned()))
-            .set_auth_uri(AuthUrl::new(authorization_url.to_owned()).with_context(|| {
+        let authorization = match authorization_url {
+            Some(url) => Some(AuthUrl::new(url.to_owned()).with_context(|| {
                 format!(
-                    "`--authorization-url` is not a correct absolute URL. Provided value: {authorization_url}",
+                    "`--authorization-url` is not a correct absolute URL. Provided value: {url}",
                 )
-            })?)
+            })?),
+            None => None,
+        };
+
+        let device_authorization = match device_authorization_url {
+            Some(url) => Some(DeviceAuthorizationUrl::new(url.to_owned()).with_context(|| {
+                format!(
+                    "`--device-authorization-url` is not a correct absolute URL. Provided value: {url}",
+                )
+            })?),
+            None => None,
+        };
+
+        let revocation = match revocation_url {
+            Some(url) => Some(RevocationUrl::new(url.to_owned()).with_context(|| {
+                format!("`--revocation-url` is not a correct absolute URL. Provided value: {url}",)
+            })?),
+            None => None,
+        };
+
+        let introspection = match introspection_url {
+            Some(url) => Some(IntrospectionUrl::new(url.to_owned()).with_context(|| {
+                format!(
+                    "`--introspection-url` is not a correct absolute URL. Provided value: {url}",
+                )
+            })?),
+            None => None,
+        };
+
+        let mut client: BaseClient = BaseClient::new(ClientId::new(args.client_id.to_owned()))
+            .set_auth_uri_option(authorization)
+            .set_device_authorization_url_option(device_authorization)
+            .set_revocation_url_option(revocation)
+            .set_introspection_url_option(introspection)
             .set_token_uri(token.unwrap());
 
-        let client_secret = args.client_secret.to_owned().map(ClientSecret::new);
+        // NOTE: JWT client authentication methods never send the secret itself
+        let client_secret = match args.client_auth_method {
+            ClientAuthMethod::ClientSecretBasic | ClientAuthMethod::ClientSecretPost => {
+                args.client_secret.to_owned().map(ClientSecret::new)
+            }
+            _ => None,
+        };
 
         if let Some(secret) = client_secret {
             client = client.set_client_secret(secret);
         }
 
+        if args.client_auth_method == ClientAuthMethod::ClientSecretPost {
+            client = client.set_auth_type(AuthType::RequestBody);
+        }
+
         if let Some(callback_url) = &args.callback_url {
             client = client.set_redirect_uri(RedirectUrl::new(callback_url.to_owned()).unwrap())
         }
@@ -71,64 +217,238 @@ impl OAuthClient<'_> {
         Ok(client)
     }
 
-    pub async fn new(args: &Arguments) -> Result<OAuthClient<'_>> {
-        log::debug!("Creating OAuthClient...");
+    async fn resolve_provider(
+        args: &Arguments,
+        certificate: &Option<ClientCertificate>,
+    ) -> Result<Provider> {
+        let (endpoints, metadata) = if let Some(discovery_url) = args.discovery_url.to_owned() {
+            log::debug!("Using `--discovery-url`={discovery_url} to get endpoints");
 
-        let (token_url, authorization_url) = if let Some(discovery_url) =
-            args.discovery_url.to_owned()
-        {
-            log::debug!(
-                "Using `--discovery-url`={discovery_url} to get token_url and authorization_url ",
-            );
+            let metadata = get_metadata_from_discovery_url(discovery_url).await?;
+
+            // NOTE: Clients using Mutual-TLS have to prefer aliased endpoints
+            let aliases = match (certificate, &metadata.mtls_endpoint_aliases) {
+                (Some(_), Some(aliases)) => aliases.to_owned(),
+                _ => MtlsEndpointAliases::default(),
+            };
 
-            let (token_url, authorization_url) =
-                get_endpoints_from_discovery_url(discovery_url).await?;
+            // NOTE: Endpoints provided explicitly take precedence over the discovered ones
+            let endpoints = Endpoints {
+                issuer: metadata.issuer.to_owned(),
+                jwks_url: args.jwks_url.to_owned().or(metadata.jwks_uri.to_owned()),
+                userinfo_url: args
+                    .userinfo_url
+                    .to_owned()
+                    .or(aliases.userinfo_endpoint)
+                    .or(metadata.userinfo_endpoint.to_owned()),
+                token_url: aliases
+                    .token_endpoint
+                    .or(Some(metadata.token_endpoint.to_owned())),
+                authorization_url: Some(metadata.authorization_endpoint.to_owned()),
+                device_authorization_url: args
+                    .device_authorization_url
+                    .to_owned()
+                    .or(aliases.device_authorization_endpoint)
+                    .or(metadata.device_authorization_endpoint.to_owned()),
+                pushed_authorization_request_url: args
+                    .pushed_authorization_request_url
+                    .to_owned()
+                    .or(aliases.pushed_authorization_request_endpoint)
+                    .or(metadata.pushed_authorization_request_endpoint.to_owned()),
+                revocation_url: args
+                    .revocation_url
+                    .to_owned()
+                    .or(aliases.revocation_endpoint)
+                    .or(metadata.revocation_endpoint.to_owned()),
+                introspection_url: args
+                    .introspection_url
+                    .to_owned()
+                    .or(aliases.introspection_endpoint)
+                    .or(metadata.introspection_endpoint.to_owned()),
+                require_pushed_authorization_requests: metadata
+                    .require_pushed_authorization_requests,
+            };
 
-            (Some(token_url), authorization_url)
+            (endpoints, Some(metadata))
         } else {
-            (
-                args.token_url.to_owned(),
-                args.authorization_url.to_owned().unwrap(),
-            )
+            let endpoints = Endpoints {
+                issuer: args.issuer.to_owned(),
+                jwks_url: args.jwks_url.to_owned(),
+                userinfo_url: args.userinfo_url.to_owned(),
+                token_url: args.token_url.to_owned(),
+                authorization_url: args.authorization_url.to_owned(),
+                device_authorization_url: args.device_authorization_url.to_owned(),
+                pushed_authorization_request_url: args.pushed_authorization_request_url.to_owned(),
+                revocation_url: args.revocation_url.to_owned(),
+                introspection_url: args.introspection_url.to_owned(),
+                require_pushed_authorization_requests: false,
+            };
+
+            (endpoints, None)
         };
 
-        log::debug!("Resolved token_url={token_url:?} and authorization_url={authorization_url}",);
+        log::debug!("Resolved endpoints: {endpoints:#?}");
 
-        let client = Self::get_client(args, token_url.as_deref(), &authorization_url)
-            .context("Failed to create a OAuthClient")?;
+        let client = Self::get_client(
+            args,
+            endpoints.token_url.as_deref(),
+            endpoints.authorization_url.as_deref(),
+            endpoints.device_authorization_url.as_deref(),
+            endpoints.revocation_url.as_deref(),
+            endpoints.introspection_url.as_deref(),
+        )
+        .context("Failed to create a OAuthClient")?;
 
-        log::debug!("OAuthClient created");
+        let pushed_authorization_request_url = if args.par
+            || endpoints.require_pushed_authorization_requests
+        {
+            let url = endpoints
+                    .pushed_authorization_request_url
+                    .context("`--pushed-authorization-request-url` has to be provided to use pushed authorization requests")?;
 
-        let http_client = reqwest::Client::builder()
-            .redirect(Policy::none())
-            .build()?;
+            Some(Url::parse(&url).with_context(|| {
+                    format!("`--pushed-authorization-request-url` is not a correct absolute URL. Provided value: {url}")
+                })?)
+        } else {
+            None
+        };
+
+        Ok(Provider {
+            inner: client,
+            pushed_authorization_request_url,
+            issuer: endpoints.issuer,
+            jwks_url: endpoints.jwks_url,
+            userinfo_url: endpoints.userinfo_url,
+            metadata,
+        })
+    }
+
+    pub fn new(args: &Arguments, dpop_key: Option<DPoPKey>) -> Result<OAuthClient<'_>> {
+        log::debug!("Creating OAuthClient...");
+
+        let certificate = ClientCertificate::from_args(args)?;
+
+        let mut http_client = reqwest::Client::builder().redirect(Policy::none());
+
+        if let Some(certificate) = &certificate {
+            log::debug!(
+                "Using client certificate with thumbprint {}",
+                certificate.thumbprint()
+            );
+            http_client = http_client.identity(certificate.identity()?);
+        }
+
+        log::debug!("OAuthClient created");
 
         Ok(OAuthClient {
             args,
-            inner: client,
-            http: http_client,
+            http: HttpClient::new(http_client.build()?, dpop_key),
+            certificate,
+            provider: OnceCell::new(),
         })
     }
 
-    fn authorization_url_builder(&self) -> AuthorizationRequest<'_> {
+    async fn provider(&self) -> Result<&Provider> {
+        self.provider
+            .get_or_try_init(|| Self::resolve_provider(self.args, &self.certificate))
+            .await
+    }
+
+    /// Checks the grant and the client authentication method against the discovery metadata.
+    /// It's done only before running the grant, as refreshing, revoking or introspecting tokens
+    /// doesn't depend on it
+    pub async fn assert_supports_grant(&self) -> Result<()> {
+        let Some(metadata) = &self.provider().await?.metadata else {
+            return Ok(());
+        };
+
+        metadata.assert_supports_grant(&self.args.grant)?;
+
+        // NOTE: Implicit grant never calls the token endpoint
+        if !matches!(self.args.grant, Grant::Implicit) {
+            metadata.assert_supports_client_auth_method(
+                &self
+                    .args
+                    .client_auth_method
+                    .effective(self.args.client_secret.as_deref()),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn jwks_url(&self) -> Result<Option<&str>> {
+        Ok(self.provider().await?.jwks_url.as_deref())
+    }
+
+    /// Validates ID token of the response when the provider returned it
+    async fn validate_id_token(
+        &self,
+        response: &DokenTokenResponse,
+        nonce: Option<&str>,
+        code: Option<&str>,
+    ) -> Result<()> {
+        let Some(id_token) = &response.extra_fields().id_token else {
+            return Ok(());
+        };
+
+        if self.args.skip_id_token_validation {
+            log::debug!("Skipping ID token validation");
+            return Ok(());
+        }
+
+        let provider = self.provider().await?;
+        let (Some(issuer), Some(jwks_url)) = (&provider.issuer, &provider.jwks_url) else {
+            bail!(
+                "ID token cannot be validated without `--issuer` and `--jwks-url`. Please provide them, use `--discovery-url` or `--skip-id-token-validation`."
+            );
+        };
+
+        IdTokenValidation {
+            issuer,
+            jwks_url,
+            client_id: &self.args.client_id,
+            client_secret: self.args.client_secret.as_deref(),
+            nonce,
+            access_token: response.access_token().secret(),
+            code,
+        }
+        .validate(id_token)
+        .await
+        .context("Failed to validate ID token")?;
+
+        Ok(())
+    }
+
+    /// Thumbprint of the certificate that tokens are bound to while using Mutual-TLS
+    pub fn certificate_thumbprint(&self) -> Option<String> {
+        self.certificate
+            .as_ref()
+            .map(|certificate| certificate.thumbprint().to_owned())
+    }
+
+    async fn authorization_url_builder(&self) -> Result<AuthorizationRequest<'_>> {
         let mut builder = self
+            .provider()
+            .await?
             .inner
             .authorize_url(CsrfToken::new_random)
+            .context("`--authorization-url` has to be provided for this grant")?
             .add_scope(Scope::new(self.args.scope.to_string()));
 
         if let Some(ref aud) = self.args.audience {
             builder = builder.add_extra_param("audience", aud);
         }
 
-        builder
+        Ok(builder)
     }
 
-    pub fn authorize_url(
+    pub async fn authorize_url(
         &self,
         pkce_challenge: Option<PkceCodeChallenge>,
-    ) -> (Url, CsrfToken, String) {
+    ) -> Result<(Url, CsrfToken, String)> {
         let nonce = Alphanumeric.sample_string(&mut rand::rng(), 16);
-        let mut builder = self.authorization_url_builder();
+        let mut builder = self.authorization_url_builder().await?;
 
         builder = builder.add_extra_param("nonce", nonce.to_owned());
 
@@ -136,42 +456,182 @@ impl OAuthClient<'_> {
             builder = builder.set_pkce_challenge(challenge);
         }
 
+        // NOTE: Binds the authorization code to the DPoP key. More: <https://www.rfc-editor.org/rfc/rfc9449#section-10>
+        if let Some(key) = self.http.dpop_key() {
+            builder = builder.add_extra_param("dpop_jkt", key.thumbprint());
+        }
+
         let (url, csrf) = builder.url();
+        let url = self.secure_authorization_url(url).await?;
+
+        let url = match &self.provider().await?.pushed_authorization_request_url {
+            Some(pushed_authorization_request_url) => {
+                self.push_authorization_request(pushed_authorization_request_url, url)
+                    .await?
+            }
+            None => url,
+        };
 
-        (url, csrf, nonce)
+        Ok((url, csrf, nonce))
     }
 
-    pub fn implicit_url(&self) -> (Url, CsrfToken) {
-        self.authorization_url_builder()
+    /// Pushes parameters of the authorization url and returns a short url referencing them.
+    /// More: <https://www.rfc-editor.org/rfc/rfc9126>
+    async fn push_authorization_request(
+        &self,
+        pushed_authorization_request_url: &Url,
+        mut url: Url,
+    ) -> Result<Url> {
+        log::debug!("Pushing authorization request...");
+
+        // NOTE: client_id is added by the client authentication
+        let params: Vec<(String, String)> = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(name, _)| name != "client_id")
+            .collect();
+
+        let body = self
+            .send_authenticated_form(
+                "pushed authorization request endpoint",
+                pushed_authorization_request_url.to_owned(),
+                params
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .collect(),
+            )
+            .await?;
+
+        let response = serde_json::from_str::<PushedAuthorizationResponse>(&body)
+            .context("Couldn't process json given by the pushed authorization request endpoint")?;
+
+        url.set_query(None);
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.args.client_id)
+            .append_pair("request_uri", &response.request_uri);
+
+        log::debug!("Authorization request pushed");
+        Ok(url)
+    }
+
+    pub async fn implicit_url(&self) -> Result<(Url, CsrfToken)> {
+        let (url, csrf) = self
+            .authorization_url_builder()
+            .await?
             .add_extra_param("response_mode", "form_post")
             .use_implicit_flow()
-            .url()
+            .url();
+
+        Ok((self.secure_authorization_url(url).await?, csrf))
     }
 
-    pub async fn exchange_client_credentials(&self) -> Result<BasicTokenResponse> {
+    /// Packs parameters of the authorization url into a signed request object when
+    /// `--request-object-key` is provided. More: <https://www.rfc-editor.org/rfc/rfc9101>
+    async fn secure_authorization_url(&self, mut url: Url) -> Result<Url> {
+        let Some(key_path) = &self.args.request_object_key else {
+            return Ok(url);
+        };
+
+        log::debug!("Signing authorization request object...");
+
+        let algorithm = &self.args.request_object_algorithm;
+        let key = jwt::read_encoding_key(algorithm, key_path)?;
+        let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+        let mut claims = jwt::Claims::new();
+        for (name, value) in &params {
+            claims.insert(name.to_owned(), value.to_owned().into());
+        }
+
+        let issued_at = jwt::now();
+        let mut authorization_url = url.to_owned();
+        authorization_url.set_query(None);
+
+        claims.insert("iss".to_owned(), self.args.client_id.to_owned().into());
+        claims.insert(
+            "aud".to_owned(),
+            self.provider()
+                .await?
+                .issuer
+                .to_owned()
+                .unwrap_or_else(|| authorization_url.to_string())
+                .into(),
+        );
+        claims.insert("iat".to_owned(), issued_at.into());
+        claims.insert("nbf".to_owned(), issued_at.into());
+        claims.insert(
+            "exp".to_owned(),
+            (issued_at + REQUEST_OBJECT_LIFETIME).into(),
+        );
+        claims.insert("jti".to_owned(), jwt::jti().into());
+
+        let request = jwt::sign(
+            algorithm,
+            &key,
+            self.args.request_object_key_id.as_deref(),
+            &claims,
+        )?;
+
+        // NOTE: Only client_id and the request object are left in the url. OpenID Connect requests
+        // need response_type and scope outside of the request object too.
+        // More: <https://openid.net/specs/openid-connect-core-1_0.html#RequestObject>
+        let outside_params: &[&str] = if is_openid_scope(&self.args.scope) {
+            &["client_id", "response_type", "scope"]
+        } else {
+            &["client_id"]
+        };
+
+        url.set_query(None);
+        {
+            let mut query = url.query_pairs_mut();
+
+            for (name, value) in &params {
+                if outside_params.contains(&name.as_str()) {
+                    query.append_pair(name, value);
+                }
+            }
+
+            query.append_pair("request", &request);
+        }
+
+        Ok(url)
+    }
+
+    pub async fn exchange_client_credentials(&self) -> Result<DokenTokenResponse> {
         log::debug!("Exchanging credentials for a token...");
 
         // NOTE: offline_mode doesn't make any sense for Client Credentials.
         // Replaces any usages for this scope even if provided by user
-        let scope = Scope::new(self.args.scope.to_string().replace("offline_access", ""));
+        let scope = Scope::new(without_offline_access(&self.args.scope));
 
-        let mut builder = self.inner.exchange_client_credentials().add_scope(scope);
+        let mut builder = self
+            .provider()
+            .await?
+            .inner
+            .exchange_client_credentials()
+            .add_scope(scope);
 
         if let Some(aud) = &self.args.audience {
             builder = builder.add_extra_param("audience", aud);
         }
 
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
         let token = builder
             .request_async(&self.http)
             .await
             .context("Failed to exchange of client credentials for a token")?;
         log::debug!("Exchange done");
+
+        self.validate_id_token(&token, None, None).await?;
         Ok(token)
     }
 
     pub async fn exchange_resource_owner_password_client_credentials(
         &self,
-    ) -> Result<BasicTokenResponse> {
+    ) -> Result<DokenTokenResponse> {
         log::debug!("Exchanging credentials for a token...");
 
         let username =
@@ -179,6 +639,8 @@ impl OAuthClient<'_> {
         let password =
             &ResourceOwnerPassword::new(self.args.password.as_deref().unwrap().to_owned());
         let mut builder = self
+            .provider()
+            .await?
             .inner
             .exchange_password(username, password)
             .add_scope(Scope::new(self.args.scope.to_string()));
@@ -187,11 +649,335 @@ impl OAuthClient<'_> {
             builder = builder.add_extra_param("audience", aud);
         }
 
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
         let token = builder
             .request_async(&self.http)
             .await
             .context("Failed to exchange client credentials for a token")?;
         log::debug!("Exchange done");
+
+        self.validate_id_token(&token, None, None).await?;
+        Ok(token)
+    }
+
+    pub async fn exchange_device_code(&self) -> Result<DokenTokenResponse> {
+        log::debug!("Requesting device authorization...");
+
+        let mut builder = self
+            .provider()
+            .await?
+            .inner
+            .exchange_device_code()
+            .context("`--device-authorization-url` has to be provided for `device-code` grant")?
+            .add_scope(Scope::new(self.args.scope.to_string()));
+
+        if let Some(aud) = &self.args.audience {
+            builder = builder.add_extra_param("audience", aud);
+        }
+
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
+        let details: StandardDeviceAuthorizationResponse = builder
+            .request_async(&self.http)
+            .await
+            .context("Failed to request a device authorization")?;
+
+        eprintln!(
+            "Open {} and enter the code: {}",
+            details.verification_uri().as_str(),
+            details.user_code().secret()
+        );
+
+        if let Some(uri) = details.verification_uri_complete() {
+            eprintln!("Alternatively open {}", uri.secret());
+        }
+
+        let token = self
+            .poll_device_access_token(&details)
+            .await
+            .context("Failed to exchange device code for a token")?;
+        log::debug!("Exchange done");
+
+        self.validate_id_token(&token, None, None).await?;
+        Ok(token)
+    }
+
+    /// Polls the token endpoint until the user completes the authorization. Unlike `oauth2` crate,
+    /// a new client assertion is signed for every poll. More: <https://www.rfc-editor.org/rfc/rfc8628#section-3.4>
+    async fn poll_device_access_token(
+        &self,
+        details: &StandardDeviceAuthorizationResponse,
+    ) -> Result<DokenTokenResponse> {
+        let token_url = self.provider().await?.inner.token_uri().url().to_owned();
+        let expires = Instant::now() + details.expires_in();
+        let mut interval = details.interval();
+
+        loop {
+            if Instant::now() >= expires {
+                bail!("The device code has expired before the authorization was completed");
+            }
+
+            let (status, body) = self
+                .post_authenticated_form(
+                    "token endpoint",
+                    token_url.to_owned(),
+                    vec![
+                        ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                        ("device_code", details.device_code().secret()),
+                    ],
+                )
+                .await?;
+
+            if status.is_success() {
+                return serde_json::from_str::<DokenTokenResponse>(&body)
+                    .context("Couldn't process json given by the token endpoint");
+            }
+
+            let error = serde_json::from_str::<DeviceCodeErrorResponse>(&body)
+                .with_context(|| format!("The token endpoint responded with {status}: {body}"))?;
+
+            interval = next_poll_interval(&error, interval)?;
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Parameters of `client_secret_jwt` and `private_key_jwt` client authentication methods.
+    /// A new assertion is signed for every request. More: <https://www.rfc-editor.org/rfc/rfc7523#section-2.2>
+    async fn client_assertion_params(&self) -> Result<Vec<(&'static str, String)>> {
+        let (algorithm, key) = match self.args.client_auth_method {
+            ClientAuthMethod::ClientSecretJwt => (
+                SigningAlgorithm::Hs256,
+                EncodingKey::from_secret(
+                    self.args
+                        .client_secret
+                        .as_deref()
+                        .context("`--client-secret` has to be provided for `client_secret_jwt` client authentication method")?
+                        .as_bytes(),
+                ),
+            ),
+            ClientAuthMethod::PrivateKeyJwt => (
+                self.args.client_assertion_algorithm.to_owned(),
+                jwt::read_encoding_key(
+                    &self.args.client_assertion_algorithm,
+                    self.args
+                        .client_assertion_key
+                        .as_deref()
+                        .context("`--client-assertion-key` has to be provided for `private_key_jwt` client authentication method")?,
+                )?,
+            ),
+            _ => return Ok(vec![]),
+        };
+
+        let client_id = self.args.client_id.as_str();
+        let claims = jwt::assertion_claims(
+            client_id,
+            client_id,
+            self.provider().await?.inner.token_uri().as_str(),
+            CLIENT_ASSERTION_LIFETIME,
+        );
+        let assertion = jwt::sign(
+            &algorithm,
+            &key,
+            self.args.client_assertion_key_id.as_deref(),
+            &claims,
+        )?;
+
+        Ok(vec![
+            ("client_assertion_type", CLIENT_ASSERTION_TYPE.to_owned()),
+            ("client_assertion", assertion),
+        ])
+    }
+
+    /// Posts a form authenticating the client the same way as while calling the token endpoint
+    async fn send_authenticated_form(
+        &self,
+        endpoint: &str,
+        url: Url,
+        params: Vec<(&str, &str)>,
+    ) -> Result<String> {
+        let (status, body) = self.post_authenticated_form(endpoint, url, params).await?;
+
+        if !status.is_success() {
+            bail!("The {endpoint} responded with {status}: {body}");
+        }
+
+        Ok(body)
+    }
+
+    async fn post_authenticated_form(
+        &self,
+        endpoint: &str,
+        url: Url,
+        params: Vec<(&str, &str)>,
+    ) -> Result<(StatusCode, String)> {
+        let client_assertion_params = self.client_assertion_params().await?;
+        let mut form = params;
+        let mut request = self.http.post(url).header(ACCEPT, "application/json");
+
+        match (&self.args.client_auth_method, &self.args.client_secret) {
+            // NOTE: Same as in `oauth2` crate, RFC 6749 Section 2.3.1 requires url-encoding
+            // of both values
+            (ClientAuthMethod::ClientSecretBasic, Some(secret)) => {
+                let client_id: String =
+                    form_urlencoded::byte_serialize(self.args.client_id.as_bytes()).collect();
+                let client_secret: String =
+                    form_urlencoded::byte_serialize(secret.as_bytes()).collect();
+                request = request.basic_auth(client_id, Some(client_secret));
+            }
+            (ClientAuthMethod::ClientSecretPost, Some(secret)) => {
+                form.push(("client_id", &self.args.client_id));
+                form.push(("client_secret", secret));
+            }
+            _ => {
+                form.push(("client_id", &self.args.client_id));
+                form.extend(
+                    client_assertion_params
+                        .iter()
+                        .map(|(name, value)| (*name, value.as_str())),
+                );
+            }
+        }
+
+        let response = self
+            .http
+            .execute(request.form(&form).build()?)
+            .await
+            .with_context(|| format!("Couldn't reach out to the {endpoint}"))?;
+
+        let status = response.status();
+        let body = response.text().await?;
+
+        Ok((status, body))
+    }
+
+    /// Sends a token request for grants that aren't supported by `oauth2` crate
+    async fn request_token(&self, params: Vec<(&str, &str)>) -> Result<DokenTokenResponse> {
+        let body = self
+            .send_authenticated_form(
+                "token endpoint",
+                self.provider().await?.inner.token_uri().url().to_owned(),
+                params,
+            )
+            .await?;
+
+        serde_json::from_str::<DokenTokenResponse>(&body)
+            .context("Couldn't process json given by the token endpoint")
+    }
+
+    pub async fn exchange_token(&self, subject_token: &str) -> Result<DokenTokenResponse> {
+        log::debug!("Exchanging subject token for a token...");
+
+        // NOTE: offline_mode doesn't make any sense for Token Exchange.
+        let scope = without_offline_access(&self.args.scope);
+
+        let mut params = vec![
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE),
+            ("subject_token", subject_token),
+            ("subject_token_type", self.args.subject_token_type.as_str()),
+        ];
+
+        if !scope.is_empty() {
+            params.push(("scope", &scope));
+        }
+
+        if let (Some(actor_token), Some(actor_token_type)) =
+            (&self.args.actor_token, &self.args.actor_token_type)
+        {
+            params.push(("actor_token", actor_token));
+            params.push(("actor_token_type", actor_token_type));
+        }
+
+        if let Some(requested_token_type) = &self.args.requested_token_type {
+            params.push(("requested_token_type", requested_token_type));
+        }
+
+        if let Some(resource) = &self.args.resource {
+            params.push(("resource", resource));
+        }
+
+        if let Some(aud) = &self.args.audience {
+            params.push(("audience", aud));
+        }
+
+        let token = self
+            .request_token(params)
+            .await
+            .context("Failed to exchange subject token for a token")?;
+        log::debug!("Exchange done");
+
+        self.validate_id_token(&token, None, None).await?;
+        Ok(token)
+    }
+
+    async fn jwt_bearer_assertion(&self) -> Result<String> {
+        let client_id = self.args.client_id.as_str();
+        let mut claims = jwt::assertion_claims(
+            self.args.assertion_issuer.as_deref().unwrap_or(client_id),
+            self.args.assertion_subject.as_deref().unwrap_or(client_id),
+            self.args
+                .assertion_audience
+                .as_deref()
+                .unwrap_or(self.provider().await?.inner.token_uri().as_str()),
+            self.args.assertion_lifetime,
+        );
+
+        if let Some(extra_claims) = &self.args.assertion_claims {
+            let extra_claims = serde_json::from_str::<jwt::Claims>(extra_claims)
+                .context("`--assertion-claims` is not a correct JSON object")?;
+
+            claims.extend(extra_claims);
+        }
+
+        let key = jwt::read_encoding_key(
+            &self.args.assertion_algorithm,
+            self.args
+                .assertion_key
+                .as_deref()
+                .context("`--assertion-key` has to be provided for `jwt-bearer` grant")?,
+        )?;
+
+        jwt::sign(
+            &self.args.assertion_algorithm,
+            &key,
+            self.args.assertion_key_id.as_deref(),
+            &claims,
+        )
+    }
+
+    pub async fn exchange_jwt_bearer(&self) -> Result<DokenTokenResponse> {
+        log::debug!("Exchanging JWT assertion for a token...");
+
+        let assertion = self.jwt_bearer_assertion().await?;
+
+        // NOTE: offline_mode doesn't make any sense for JWT Bearer.
+        let scope = without_offline_access(&self.args.scope);
+
+        let mut params = vec![
+            ("grant_type", JWT_BEARER_GRANT_TYPE),
+            ("assertion", assertion.as_str()),
+        ];
+
+        if !scope.is_empty() {
+            params.push(("scope", &scope));
+        }
+
+        if let Some(aud) = &self.args.audience {
+            params.push(("audience", aud));
+        }
+
+        let token = self
+            .request_token(params)
+            .await
+            .context("Failed to exchange JWT assertion for a token")?;
+        log::debug!("Exchange done");
+
+        self.validate_id_token(&token, None, None).await?;
         Ok(token)
     }
 
@@ -199,9 +985,12 @@ impl OAuthClient<'_> {
         &self,
         code: &str,
         code_verifier: Option<PkceCodeVerifier>,
-    ) -> Result<BasicTokenResponse> {
+        nonce: &str,
+    ) -> Result<DokenTokenResponse> {
         log::debug!("Exchanging code for a token...");
         let mut builder = self
+            .provider()
+            .await?
             .inner
             .exchange_code(AuthorizationCode::new(code.to_string()));
 
@@ -209,28 +998,662 @@ impl OAuthClient<'_> {
             builder = builder.set_pkce_verifier(verifier);
         }
 
-        let token: BasicTokenResponse = builder
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
+        let token: DokenTokenResponse = builder
             .request_async(&self.http)
             .await
             .context("Failed to exchange code for a token")?;
         log::debug!("Exchange done");
 
+        self.validate_id_token(&token, Some(nonce), Some(code))
+            .await?;
         Ok(token)
     }
 
-    pub async fn refresh_token(&self, refresh_token: String) -> Result<BasicTokenResponse> {
+    /// Refreshes the token. Scope narrower than the granted one can be requested.
+    /// More: <https://www.rfc-editor.org/rfc/rfc6749#section-6>
+    pub async fn refresh_token(
+        &self,
+        refresh_token: String,
+        scope: Option<&str>,
+    ) -> Result<DokenTokenResponse> {
         log::debug!("Refreshing token...");
 
         let refresh_token = RefreshToken::new(refresh_token);
 
-        let response = self
+        let mut builder = self
+            .provider()
+            .await?
             .inner
-            .exchange_refresh_token(&refresh_token)
+            .exchange_refresh_token(&refresh_token);
+
+        if let Some(scope) = scope {
+            builder = builder.add_scope(Scope::new(scope.to_owned()));
+        }
+
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
+        let response = builder
             .request_async(&self.http)
             .await
             .context("Failed to exchange refresh token to a new token")?;
 
         log::debug!("Refresh done");
+
+        self.validate_id_token(&response, None, None).await?;
+        Ok(response)
+    }
+
+    /// Revokes a refresh or an access token. More: <https://www.rfc-editor.org/rfc/rfc7009>
+    pub async fn revoke_token(&self, token: StandardRevocableToken) -> Result<()> {
+        log::debug!("Revoking token...");
+
+        let mut builder = self.provider().await?.inner.revoke_token(token).context(
+            "`--revocation-url` has to be provided to revoke tokens or the IdP doesn't support revocation",
+        )?;
+
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
+        builder
+            .request_async(&self.http)
+            .await
+            .context("Failed to revoke a token")?;
+
+        log::debug!("Revocation done");
+        Ok(())
+    }
+
+    /// Asks the IdP whether the token is active. More: <https://www.rfc-editor.org/rfc/rfc7662>
+    pub async fn introspect_token(&self, token: String) -> Result<DokenIntrospectionResponse> {
+        log::debug!("Introspecting token...");
+
+        let token = AccessToken::new(token);
+        let mut builder = self.provider().await?.inner.introspect(&token).context(
+            "`--introspection-url` has to be provided to introspect tokens or the IdP doesn't support introspection",
+        )?;
+
+        for (name, value) in self.client_assertion_params().await? {
+            builder = builder.add_extra_param(name, value);
+        }
+
+        let response = builder
+            .request_async(&self.http)
+            .await
+            .context("Failed to introspect a token")?;
+
+        log::debug!("Introspection done");
         Ok(response)
     }
+
+    /// Claims of signed UserInfo responses. `iss` and `aud` are checked when present.
+    /// More: <https://openid.net/specs/openid-connect-core-1_0.html#UserInfoResponse>
+    async fn decode_signed_userinfo(&self, userinfo: &str) -> Result<Claims> {
+        let jwks_url = self
+            .provider()
+            .await?
+            .jwks_url
+            .as_deref()
+            .context("`--jwks-url` has to be provided to verify signed UserInfo responses")?;
+
+        let header = jsonwebtoken::decode_header(userinfo)
+            .context("UserInfo response is not a correct JWT")?;
+        let key = JwksCache::new(jwks_url)
+            .decoding_key(
+                header.alg,
+                header.kid.as_deref(),
+                self.args.client_secret.as_deref(),
+            )
+            .await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        validation.required_spec_claims.clear();
+
+        let claims = jsonwebtoken::decode::<Claims>(userinfo, &key, &validation)
+            .context("UserInfo response signature is invalid")?
+            .claims;
+
+        if let (Some(iss), Some(issuer)) = (claims.get("iss"), &self.provider().await?.issuer)
+            && iss.as_str() != Some(issuer)
+        {
+            bail!("UserInfo response was issued by other issuer: {iss}");
+        }
+
+        let client_id = serde_json::Value::from(self.args.client_id.as_str());
+        if let Some(aud) = claims.get("aud")
+            && aud != &client_id
+            && !aud.as_array().is_some_and(|aud| aud.contains(&client_id))
+        {
+            bail!("UserInfo response was issued to other client: {aud}");
+        }
+
+        Ok(claims)
+    }
+
+    /// Claims about the authenticated user. More: <https://openid.net/specs/openid-connect-core-1_0.html#UserInfo>
+    pub async fn userinfo(&self, access_token: &str) -> Result<Claims> {
+        log::debug!("Fetching UserInfo...");
+
+        let url = self.provider().await?.userinfo_url.as_deref().context(
+            "`--userinfo-url` has to be provided to fetch UserInfo or the IdP doesn't support it",
+        )?;
+        let url = Url::parse(url).with_context(|| {
+            format!("`--userinfo-url` is not a correct absolute URL. Provided value: {url}")
+        })?;
+
+        let scheme = match self.http.dpop_key() {
+            Some(_) => "DPoP",
+            None => "Bearer",
+        };
+
+        let request = self
+            .http
+            .get(url)
+            .header(AUTHORIZATION, format!("{scheme} {access_token}"))
+            .build()?;
+
+        let response = self
+            .http
+            .execute_with_access_token(request, Some(access_token))
+            .await
+            .context("Couldn't reach out to the UserInfo endpoint")?;
+
+        let status = response.status();
+        let is_signed = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with(JWT_CONTENT_TYPE));
+        let body = response.text().await?;
+
+        if !status.is_success() {
+            bail!("The UserInfo endpoint responded with {status}: {body}");
+        }
+
+        log::debug!("UserInfo fetched");
+
+        if is_signed {
+            return self.decode_signed_userinfo(body.trim()).await;
+        }
+
+        serde_json::from_str::<Claims>(&body)
+            .context("Couldn't process json given by the UserInfo endpoint")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use crate::test_server::{self, test_data};
+    use jsonwebtoken::{DecodingKey, Header};
+    use std::fs;
+
+    use super::*;
+
+    fn device_code_error(error: &str) -> DeviceCodeErrorResponse {
+        serde_json::from_value(serde_json::json!({ "error": error })).unwrap()
+    }
+
+    fn device_authorization_response(expires_in: u64) -> (u16, String) {
+        (
+            200,
+            serde_json::json!({
+                "device_code": "test-device-code",
+                "user_code": "TEST-CODE",
+                "verification_uri": "https://my-idp.com/device",
+                "expires_in": expires_in,
+                "interval": 0,
+            })
+            .to_string(),
+        )
+    }
+
+    fn token_response() -> (u16, String) {
+        (
+            200,
+            r#"{"access_token":"test-access-token","token_type":"Bearer"}"#.to_owned(),
+        )
+    }
+
+    fn get_device_code_args(url: &str) -> Arguments {
+        Arguments {
+            grant: Grant::DeviceCode,
+            client_id: "test-client-id".to_owned(),
+            token_url: Some(format!("{url}/token")),
+            device_authorization_url: Some(format!("{url}/device")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_keeps_polling_while_authorization_is_pending() {
+        let interval = Duration::from_secs(1);
+
+        assert_eq!(
+            next_poll_interval(&device_code_error("authorization_pending"), interval).unwrap(),
+            interval
+        );
+    }
+
+    #[test]
+    fn it_slows_down_polling_when_asked() {
+        let interval = Duration::from_secs(1);
+
+        assert_eq!(
+            next_poll_interval(&device_code_error("slow_down"), interval).unwrap(),
+            Duration::from_secs(6)
+        );
+    }
+
+    #[test]
+    fn it_stops_polling_on_other_errors() {
+        let interval = Duration::from_secs(1);
+
+        assert!(next_poll_interval(&device_code_error("access_denied"), interval).is_err());
+        assert!(next_poll_interval(&device_code_error("expired_token"), interval).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_polls_until_the_device_is_authorized() {
+        let (url, requests) = test_server::serve(vec![
+            device_authorization_response(60),
+            (400, r#"{"error":"authorization_pending"}"#.to_owned()),
+            (400, r#"{"error":"authorization_pending"}"#.to_owned()),
+            token_response(),
+        ])
+        .await;
+        let args = get_device_code_args(&url);
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let token = oauth_client.exchange_device_code().await.unwrap();
+        let requests = requests.await.unwrap();
+
+        assert_eq!(token.access_token().secret(), "test-access-token");
+        assert_eq!(requests.len(), 4);
+        for poll in &requests[1..] {
+            assert_eq!(
+                test_server::form_param(poll, "grant_type").as_deref(),
+                Some(DEVICE_CODE_GRANT_TYPE)
+            );
+            assert_eq!(
+                test_server::form_param(poll, "device_code").as_deref(),
+                Some("test-device-code")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn it_stops_polling_once_the_device_code_expires() {
+        let (url, _) = test_server::serve(vec![device_authorization_response(0)]).await;
+        let args = get_device_code_args(&url);
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let error = oauth_client.exchange_device_code().await.unwrap_err();
+
+        assert!(format!("{error:#}").contains("expired"));
+    }
+
+    /// Key verifying JWTs signed with the keys in `tests/data`
+    fn test_decoding_key(algorithm: &SigningAlgorithm) -> DecodingKey {
+        match algorithm {
+            SigningAlgorithm::Rs256 | SigningAlgorithm::Ps256 => {
+                DecodingKey::from_rsa_pem(&fs::read(test_data("rsa_public_key.pem")).unwrap())
+            }
+            SigningAlgorithm::Es256 => {
+                DecodingKey::from_ec_pem(&fs::read(test_data("ec_public_key.pem")).unwrap())
+            }
+            SigningAlgorithm::Hs256 => Ok(DecodingKey::from_secret(
+                fs::read(test_data("hmac_secret.txt"))
+                    .unwrap()
+                    .trim_ascii_end(),
+            )),
+        }
+        .unwrap()
+    }
+
+    fn test_private_key(algorithm: &SigningAlgorithm) -> String {
+        test_data(match algorithm {
+            SigningAlgorithm::Rs256 | SigningAlgorithm::Ps256 => "rsa_private_key.pem",
+            SigningAlgorithm::Es256 => "ec_private_key.pem",
+            SigningAlgorithm::Hs256 => "hmac_secret.txt",
+        })
+    }
+
+    /// Checks the signature and the expiration of the JWT and returns its header and claims
+    fn decode_jwt(jwt: &str, algorithm: &SigningAlgorithm) -> (Header, Claims) {
+        let mut validation = Validation::new(algorithm.algorithm());
+        validation.validate_aud = false;
+
+        let token = jsonwebtoken::decode::<Claims>(jwt, &test_decoding_key(algorithm), &validation)
+            .unwrap();
+
+        (token.header, token.claims)
+    }
+
+    fn get_jwt_bearer_args(algorithm: SigningAlgorithm) -> Arguments {
+        Arguments {
+            grant: Grant::JwtBearer,
+            client_id: "test-client-id".to_owned(),
+            token_url: Some("https://my-idp.com/token".to_owned()),
+            assertion_key: Some(test_private_key(&algorithm)),
+            assertion_algorithm: algorithm,
+            assertion_lifetime: 300,
+            ..Default::default()
+        }
+    }
+
+    async fn assert_jwt_bearer_assertion(algorithm: SigningAlgorithm) {
+        let args = Arguments {
+            assertion_key_id: Some("test-key-id".to_owned()),
+            assertion_issuer: Some("test-issuer".to_owned()),
+            assertion_subject: Some("test-subject".to_owned()),
+            assertion_audience: Some("https://my-idp.com".to_owned()),
+            assertion_claims: Some(r#"{"tenant":"test-tenant","roles":["admin"]}"#.to_owned()),
+            ..get_jwt_bearer_args(algorithm.to_owned())
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let assertion = oauth_client.jwt_bearer_assertion().await.unwrap();
+        let (header, claims) = decode_jwt(&assertion, &algorithm);
+
+        assert_eq!(header.alg, algorithm.algorithm());
+        assert_eq!(header.kid.as_deref(), Some("test-key-id"));
+        assert_eq!(claims["iss"], "test-issuer");
+        assert_eq!(claims["sub"], "test-subject");
+        assert_eq!(claims["aud"], "https://my-idp.com");
+        assert_eq!(claims["tenant"], "test-tenant");
+        assert_eq!(claims["roles"], serde_json::json!(["admin"]));
+        assert_eq!(
+            claims["exp"].as_u64().unwrap(),
+            claims["iat"].as_u64().unwrap() + 300
+        );
+        assert!(claims["jti"].as_str().is_some_and(|jti| !jti.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn it_signs_jwt_bearer_assertion_with_rs256() {
+        assert_jwt_bearer_assertion(SigningAlgorithm::Rs256).await;
+    }
+
+    #[tokio::test]
+    async fn it_signs_jwt_bearer_assertion_with_ps256() {
+        assert_jwt_bearer_assertion(SigningAlgorithm::Ps256).await;
+    }
+
+    #[tokio::test]
+    async fn it_signs_jwt_bearer_assertion_with_es256() {
+        assert_jwt_bearer_assertion(SigningAlgorithm::Es256).await;
+    }
+
+    #[tokio::test]
+    async fn it_signs_jwt_bearer_assertion_with_hs256() {
+        assert_jwt_bearer_assertion(SigningAlgorithm::Hs256).await;
+    }
+
+    #[tokio::test]
+    async fn it_defaults_jwt_bearer_assertion_to_the_client_and_the_token_endpoint() {
+        let args = get_jwt_bearer_args(SigningAlgorithm::Rs256);
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let assertion = oauth_client.jwt_bearer_assertion().await.unwrap();
+        let (header, claims) = decode_jwt(&assertion, &SigningAlgorithm::Rs256);
+
+        assert_eq!(header.kid, None);
+        assert_eq!(claims["iss"], "test-client-id");
+        assert_eq!(claims["sub"], "test-client-id");
+        assert_eq!(claims["aud"], "https://my-idp.com/token");
+    }
+
+    #[tokio::test]
+    async fn it_rejects_assertion_claims_other_than_an_object() {
+        let args = Arguments {
+            assertion_claims: Some("[]".to_owned()),
+            ..get_jwt_bearer_args(SigningAlgorithm::Hs256)
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        assert!(oauth_client.jwt_bearer_assertion().await.is_err());
+    }
+
+    fn get_authorization_args(url: &str) -> Arguments {
+        Arguments {
+            grant: Grant::AuthorizationCodeWithPkce,
+            client_id: "test-client-id".to_owned(),
+            authorization_url: Some(format!("{url}/authorize")),
+            token_url: Some(format!("{url}/token")),
+            callback_url: Some("http://127.0.0.1:8080/callback".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn query_names(url: &Url) -> Vec<String> {
+        url.query_pairs()
+            .map(|(name, _)| name.into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_references_pushed_authorization_request_in_the_url() {
+        let (url, requests) = test_server::serve(vec![(
+            201,
+            r#"{"request_uri":"urn:ietf:params:oauth:request_uri:test","expires_in":60}"#
+                .to_owned(),
+        )])
+        .await;
+        let args = Arguments {
+            par: true,
+            pushed_authorization_request_url: Some(format!("{url}/par")),
+            ..get_authorization_args(&url)
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let (authorization_url, csrf, nonce) = oauth_client.authorize_url(None).await.unwrap();
+        let requests = requests.await.unwrap();
+
+        assert_eq!(authorization_url.path(), "/authorize");
+        assert_eq!(
+            query_names(&authorization_url),
+            ["client_id", "request_uri"]
+        );
+        assert!(
+            authorization_url
+                .query_pairs()
+                .any(|(name, value)| name == "request_uri"
+                    && value == "urn:ietf:params:oauth:request_uri:test")
+        );
+        assert!(requests[0].starts_with("POST /par "));
+        assert_eq!(
+            test_server::form_param(&requests[0], "client_id").as_deref(),
+            Some("test-client-id")
+        );
+        assert_eq!(
+            test_server::form_param(&requests[0], "response_type").as_deref(),
+            Some("code")
+        );
+        assert_eq!(
+            test_server::form_param(&requests[0], "state").as_deref(),
+            Some(csrf.secret().as_str())
+        );
+        assert_eq!(test_server::form_param(&requests[0], "nonce"), Some(nonce));
+    }
+
+    fn get_request_object_args(url: &str, scope: &str) -> Arguments {
+        Arguments {
+            scope: scope.to_owned(),
+            request_object_key: Some(test_private_key(&SigningAlgorithm::Hs256)),
+            request_object_algorithm: SigningAlgorithm::Hs256,
+            ..get_authorization_args(url)
+        }
+    }
+
+    fn query_param(url: &Url, name: &str) -> Option<String> {
+        url.query_pairs()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[tokio::test]
+    async fn it_moves_authorization_params_into_request_object() {
+        let args = get_request_object_args("https://my-idp.com", "email offline_access");
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let (url, csrf, nonce) = oauth_client.authorize_url(None).await.unwrap();
+        let request = query_param(&url, "request").unwrap();
+        let (_, claims) = decode_jwt(&request, &SigningAlgorithm::Hs256);
+
+        assert_eq!(query_names(&url), ["client_id", "request"]);
+        assert_eq!(
+            query_param(&url, "client_id").as_deref(),
+            Some("test-client-id")
+        );
+        assert_eq!(claims["iss"], "test-client-id");
+        assert_eq!(claims["aud"], "https://my-idp.com/authorize");
+        assert_eq!(claims["client_id"], "test-client-id");
+        assert_eq!(claims["response_type"], "code");
+        assert_eq!(claims["scope"], "email offline_access");
+        assert_eq!(claims["redirect_uri"], "http://127.0.0.1:8080/callback");
+        assert_eq!(claims["state"], csrf.secret().as_str());
+        assert_eq!(claims["nonce"], nonce.as_str());
+    }
+
+    #[tokio::test]
+    async fn it_keeps_response_type_and_scope_outside_of_openid_request_object() {
+        let args = get_request_object_args("https://my-idp.com", "openid email");
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        let (url, csrf, _) = oauth_client.authorize_url(None).await.unwrap();
+        let request = query_param(&url, "request").unwrap();
+        let (_, claims) = decode_jwt(&request, &SigningAlgorithm::Hs256);
+
+        assert_eq!(
+            query_names(&url),
+            ["response_type", "client_id", "scope", "request"]
+        );
+        assert_eq!(query_param(&url, "scope").as_deref(), Some("openid email"));
+        assert_eq!(claims["scope"], "openid email");
+        assert_eq!(claims["state"], csrf.secret().as_str());
+    }
+
+    /// Decoded `client_assertion` and checks of the claims shared by every client assertion
+    async fn assert_client_assertion(
+        oauth_client: &OAuthClient<'_>,
+        algorithm: &SigningAlgorithm,
+        key: &DecodingKey,
+    ) -> Claims {
+        let params = oauth_client.client_assertion_params().await.unwrap();
+        let [
+            ("client_assertion_type", assertion_type),
+            ("client_assertion", assertion),
+        ] = &params[..]
+        else {
+            panic!("Unexpected client assertion params {params:?}");
+        };
+        assert_eq!(assertion_type, CLIENT_ASSERTION_TYPE);
+
+        let mut validation = Validation::new(algorithm.algorithm());
+        validation.set_audience(&["https://my-idp.com/token"]);
+        validation.set_issuer(&["test-client-id"]);
+        validation.sub = Some("test-client-id".to_owned());
+        let claims = jsonwebtoken::decode::<Claims>(assertion, key, &validation)
+            .unwrap()
+            .claims;
+
+        let now = jwt::now();
+        let exp = claims["exp"].as_u64().unwrap();
+        assert!(exp > now && exp <= now + CLIENT_ASSERTION_LIFETIME);
+
+        claims
+    }
+
+    #[tokio::test]
+    async fn it_signs_client_assertion_with_client_secret() {
+        let args = Arguments {
+            client_id: "test-client-id".to_owned(),
+            client_secret: Some("test-client-secret-of-at-least-32-bytes".to_owned()),
+            client_auth_method: ClientAuthMethod::ClientSecretJwt,
+            token_url: Some("https://my-idp.com/token".to_owned()),
+            ..Default::default()
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+        let key = DecodingKey::from_secret(b"test-client-secret-of-at-least-32-bytes");
+
+        let first = assert_client_assertion(&oauth_client, &SigningAlgorithm::Hs256, &key).await;
+        let second = assert_client_assertion(&oauth_client, &SigningAlgorithm::Hs256, &key).await;
+
+        assert_ne!(first["jti"], second["jti"]);
+    }
+
+    #[tokio::test]
+    async fn it_signs_client_assertion_with_private_key() {
+        let args = Arguments {
+            client_id: "test-client-id".to_owned(),
+            client_auth_method: ClientAuthMethod::PrivateKeyJwt,
+            client_assertion_key: Some(test_private_key(&SigningAlgorithm::Es256)),
+            client_assertion_algorithm: SigningAlgorithm::Es256,
+            token_url: Some("https://my-idp.com/token".to_owned()),
+            ..Default::default()
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+        let key = test_decoding_key(&SigningAlgorithm::Es256);
+
+        let first = assert_client_assertion(&oauth_client, &SigningAlgorithm::Es256, &key).await;
+        let second = assert_client_assertion(&oauth_client, &SigningAlgorithm::Es256, &key).await;
+
+        assert_ne!(first["jti"], second["jti"]);
+    }
+
+    #[tokio::test]
+    async fn it_skips_client_assertion_for_other_client_auth_methods() {
+        let args = Arguments {
+            client_id: "test-client-id".to_owned(),
+            client_secret: Some("test-client-secret".to_owned()),
+            token_url: Some("https://my-idp.com/token".to_owned()),
+            ..Default::default()
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        assert!(
+            oauth_client
+                .client_assertion_params()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_signs_new_client_assertion_for_every_poll() {
+        let (url, requests) = test_server::serve(vec![
+            device_authorization_response(60),
+            (400, r#"{"error":"authorization_pending"}"#.to_owned()),
+            token_response(),
+        ])
+        .await;
+        let args = Arguments {
+            client_secret: Some("test-client-secret-of-at-least-32-bytes".to_owned()),
+            client_auth_method: ClientAuthMethod::ClientSecretJwt,
+            ..get_device_code_args(&url)
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+
+        oauth_client.exchange_device_code().await.unwrap();
+        let assertions = requests
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| test_server::form_param(request, "client_assertion").unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(assertions.len(), 3);
+        assert_ne!(assertions[1], assertions[2]);
+    }
 }
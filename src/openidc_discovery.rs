@@ -1,22 +1,405 @@
-use anyhow::{Context, Result};
+use crate::client_auth_method::ClientAuthMethod;
+use crate::file_cache::cache_file_path;
+use crate::grant::Grant;
+use anyhow::{Context, Result, bail};
+use chrono::DateTime;
+use reqwest::header::{CACHE_CONTROL, EXPIRES, HeaderMap};
 use serde::{Deserialize, Serialize};
+use serde_variant::to_variant_name;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
 
+/// Lifetime of cached metadata when the provider doesn't send caching headers
+const DEFAULT_CACHE_TTL: u64 = 3600;
+const PKCE_METHOD: &str = "S256";
+
+/// Endpoints used by clients authenticating with Mutual-TLS. More: <https://www.rfc-editor.org/rfc/rfc8705#section-5>
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct MtlsEndpointAliases {
+    pub token_endpoint: Option<String>,
+
+    pub device_authorization_endpoint: Option<String>,
+
+    pub pushed_authorization_request_endpoint: Option<String>,
+
+    pub revocation_endpoint: Option<String>,
+
+    pub introspection_endpoint: Option<String>,
+
+    pub userinfo_endpoint: Option<String>,
+}
+
+/// OpenID Provider and OAuth 2.0 Authorization Server metadata.
+/// More: <https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata> and <https://www.rfc-editor.org/rfc/rfc8414#section-2>
 #[derive(Deserialize, Serialize, Debug)]
-struct OpenIDProviderMetadata {
-    token_endpoint: String,
+pub struct OpenIDProviderMetadata {
+    pub issuer: Option<String>,
+
+    pub token_endpoint: String,
+
+    pub authorization_endpoint: String,
+
+    pub device_authorization_endpoint: Option<String>,
+
+    pub jwks_uri: Option<String>,
+
+    /// More: <https://openid.net/specs/openid-connect-core-1_0.html#UserInfo>
+    pub userinfo_endpoint: Option<String>,
+
+    pub registration_endpoint: Option<String>,
+
+    /// More: <https://openid.net/specs/openid-connect-rpinitiated-1_0.html#OPMetadata>
+    pub end_session_endpoint: Option<String>,
+
+    pub check_session_iframe: Option<String>,
+
+    /// More: <https://www.rfc-editor.org/rfc/rfc9126#section-5>
+    pub pushed_authorization_request_endpoint: Option<String>,
+
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+
+    /// More: <https://www.rfc-editor.org/rfc/rfc8414#section-2>
+    pub revocation_endpoint: Option<String>,
+
+    pub revocation_endpoint_auth_methods_supported: Option<Vec<String>>,
+
+    /// More: <https://www.rfc-editor.org/rfc/rfc8414#section-2>
+    pub introspection_endpoint: Option<String>,
+
+    pub introspection_endpoint_auth_methods_supported: Option<Vec<String>>,
+
+    pub scopes_supported: Option<Vec<String>>,
+
+    pub response_types_supported: Option<Vec<String>>,
 
-    authorization_endpoint: String,
+    pub response_modes_supported: Option<Vec<String>>,
+
+    pub grant_types_supported: Option<Vec<String>>,
+
+    pub subject_types_supported: Option<Vec<String>>,
+
+    pub acr_values_supported: Option<Vec<String>>,
+
+    pub claims_supported: Option<Vec<String>>,
+
+    pub token_endpoint_auth_methods_supported: Option<Vec<String>>,
+
+    pub token_endpoint_auth_signing_alg_values_supported: Option<Vec<String>>,
+
+    pub id_token_signing_alg_values_supported: Option<Vec<String>>,
+
+    pub userinfo_signing_alg_values_supported: Option<Vec<String>>,
+
+    pub request_object_signing_alg_values_supported: Option<Vec<String>>,
+
+    /// More: <https://www.rfc-editor.org/rfc/rfc7636#section-4.3>
+    pub code_challenge_methods_supported: Option<Vec<String>>,
+
+    /// More: <https://www.rfc-editor.org/rfc/rfc9449#section-5.1>
+    pub dpop_signing_alg_values_supported: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub request_parameter_supported: bool,
+
+    #[serde(default)]
+    pub claims_parameter_supported: bool,
+
+    #[serde(default)]
+    pub tls_client_certificate_bound_access_tokens: bool,
+
+    pub mtls_endpoint_aliases: Option<MtlsEndpointAliases>,
 }
 
-pub async fn get_endpoints_from_discovery_url(discovery_url: String) -> Result<(String, String)> {
-    let result = reqwest::get(discovery_url.to_owned())
+#[derive(Deserialize, Serialize)]
+struct CachedMetadata {
+    expires: SystemTime,
+    metadata: OpenIDProviderMetadata,
+}
+
+/// Fails when the provider lists supported values, but the value isn't one of them
+fn assert_supported(supported: &Option<Vec<String>>, value: &str, what: &str) -> Result<()> {
+    match supported {
+        Some(supported) if !supported.iter().any(|supported| supported == value) => bail!(
+            "The IdP doesn't support `{value}` {what}. Supported ones: {}",
+            supported.join(", ")
+        ),
+        _ => Ok(()),
+    }
+}
+
+impl OpenIDProviderMetadata {
+    /// Checks the grant against the metadata. Omitted lists are treated as unknown rather than
+    /// as defaults of the specification, as providers tend to skip them
+    pub fn assert_supports_grant(&self, grant: &Grant) -> Result<()> {
+        assert_supported(
+            &self.grant_types_supported,
+            grant.grant_type(),
+            "grant type",
+        )?;
+
+        if matches!(grant, Grant::AuthorizationCodeWithPkce) {
+            assert_supported(
+                &self.code_challenge_methods_supported,
+                PKCE_METHOD,
+                "PKCE code challenge method",
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn assert_supports_client_auth_method(
+        &self,
+        client_auth_method: &ClientAuthMethod,
+    ) -> Result<()> {
+        // NOTE: Providers rarely list `none` even when they allow public clients
+        if *client_auth_method == ClientAuthMethod::None {
+            return Ok(());
+        }
+
+        assert_supported(
+            &self.token_endpoint_auth_methods_supported,
+            to_variant_name(client_auth_method).unwrap(),
+            "client authentication method",
+        )
+    }
+}
+
+/// Lifetime given by `Cache-Control` or `Expires` headers. More: <https://www.rfc-editor.org/rfc/rfc9111#section-4.2.1>
+fn cache_ttl(headers: &HeaderMap) -> u64 {
+    let cache_control = headers
+        .get(CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    for directive in cache_control.split(',').map(str::trim) {
+        if directive == "no-store" || directive == "no-cache" {
+            return 0;
+        }
+
+        if let Some(max_age) = directive.strip_prefix("max-age=") {
+            return max_age.parse().unwrap_or(0);
+        }
+    }
+
+    headers
+        .get(EXPIRES)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(|expires| (expires.timestamp() - chrono::Utc::now().timestamp()).max(0) as u64)
+        .unwrap_or(DEFAULT_CACHE_TTL)
+}
+
+async fn read_cache(discovery_url: &str) -> Option<OpenIDProviderMetadata> {
+    let content = fs::read_to_string(cache_file_path("discovery", discovery_url))
+        .await
+        .ok()?;
+    let cached = serde_json::from_str::<CachedMetadata>(&content).ok()?;
+
+    if cached.expires < SystemTime::now() {
+        log::debug!("Cached discovery metadata has expired");
+        return None;
+    }
+
+    Some(cached.metadata)
+}
+
+async fn write_cache(discovery_url: &str, cached: &CachedMetadata) -> Result<()> {
+    let file_path = cache_file_path("discovery", discovery_url);
+
+    if let Some(cache_dir) = file_path.parent() {
+        fs::create_dir_all(cache_dir).await?;
+    }
+
+    fs::write(file_path, serde_json::to_string(cached)?)
+        .await
+        .context("Failed to write discovery cache")
+}
+
+/// Reads the metadata from ~/.doken/cache directory or fetches it when it's missing or stale
+pub async fn get_metadata_from_discovery_url(
+    discovery_url: String,
+) -> Result<OpenIDProviderMetadata> {
+    if let Some(metadata) = read_cache(&discovery_url).await {
+        log::debug!("Discovery metadata found in the cache");
+        return Ok(metadata);
+    }
+
+    let response = reqwest::get(discovery_url.to_owned())
         .await
         .context("Couldn't reach out to provided `--discovery-url`")?
         .error_for_status()
-        .context("Failed during OIDC discovery call")?
+        .context("Failed during OIDC discovery call")?;
+
+    let ttl = cache_ttl(response.headers());
+
+    let metadata = response
         .json::<OpenIDProviderMetadata>()
         .await
         .context("Couldn't process json given by `--discovery-url`")?;
 
-    Ok((result.token_endpoint, result.authorization_endpoint))
+    if ttl == 0 {
+        return Ok(metadata);
+    }
+
+    let cached = CachedMetadata {
+        expires: SystemTime::now() + Duration::from_secs(ttl),
+        metadata,
+    };
+    // NOTE: The cache only saves a network call, so the metadata is still used when it cannot be written
+    if let Err(e) = write_cache(&discovery_url, &cached).await {
+        log::warn!("Couldn't cache discovery metadata: {e:#}");
+    }
+
+    Ok(cached.metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+
+    fn get_metadata(supported: serde_json::Value) -> OpenIDProviderMetadata {
+        let mut metadata = json!({
+            "token_endpoint": "https://my-idp.com/token",
+            "authorization_endpoint": "https://my-idp.com/authorize",
+        });
+        metadata
+            .as_object_mut()
+            .unwrap()
+            .extend(supported.as_object().unwrap().to_owned());
+
+        serde_json::from_value(metadata).unwrap()
+    }
+
+    fn get_headers(headers: &[(reqwest::header::HeaderName, &'static str)]) -> HeaderMap {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(name, HeaderValue::from_static(value));
+        }
+        header_map
+    }
+
+    #[test]
+    fn it_takes_cache_ttl_from_max_age() {
+        let headers = get_headers(&[(CACHE_CONTROL, "public, max-age=60")]);
+
+        assert_eq!(cache_ttl(&headers), 60);
+    }
+
+    #[test]
+    fn it_does_not_cache_when_forbidden() {
+        let no_store = get_headers(&[(CACHE_CONTROL, "no-store")]);
+        let no_cache = get_headers(&[(CACHE_CONTROL, "no-cache, max-age=60")]);
+
+        assert_eq!(cache_ttl(&no_store), 0);
+        assert_eq!(cache_ttl(&no_cache), 0);
+    }
+
+    #[test]
+    fn it_takes_cache_ttl_from_expires() {
+        let expired = get_headers(&[(EXPIRES, "Thu, 01 Jan 1970 00:00:00 GMT")]);
+        let future = get_headers(&[(EXPIRES, "Fri, 01 Jan 2100 00:00:00 GMT")]);
+
+        assert_eq!(cache_ttl(&expired), 0);
+        assert!(cache_ttl(&future) > DEFAULT_CACHE_TTL);
+    }
+
+    #[test]
+    fn it_falls_back_to_default_cache_ttl() {
+        assert_eq!(cache_ttl(&HeaderMap::new()), DEFAULT_CACHE_TTL);
+    }
+
+    #[test]
+    fn it_accepts_grant_listed_by_the_idp() {
+        let metadata = get_metadata(json!({
+            "grant_types_supported": ["authorization_code", "refresh_token"],
+            "code_challenge_methods_supported": ["plain", "S256"],
+        }));
+
+        assert!(
+            metadata
+                .assert_supports_grant(&Grant::AuthorizationCodeWithPkce)
+                .is_ok()
+        );
+        assert!(
+            metadata
+                .assert_supports_grant(&Grant::ClientCredentials)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn it_rejects_pkce_without_s256() {
+        let metadata = get_metadata(json!({
+            "code_challenge_methods_supported": ["plain"],
+        }));
+
+        assert!(
+            metadata
+                .assert_supports_grant(&Grant::AuthorizationCodeWithPkce)
+                .is_err()
+        );
+        assert!(
+            metadata
+                .assert_supports_grant(&Grant::AuthorizationCode)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn it_accepts_anything_when_the_idp_lists_nothing() {
+        let metadata = get_metadata(json!({}));
+
+        assert!(metadata.assert_supports_grant(&Grant::DeviceCode).is_ok());
+        assert!(
+            metadata
+                .assert_supports_client_auth_method(&ClientAuthMethod::PrivateKeyJwt)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn it_checks_effective_client_auth_method() {
+        let metadata = get_metadata(json!({
+            "token_endpoint_auth_methods_supported": ["none", "private_key_jwt"],
+        }));
+        let public_client = ClientAuthMethod::ClientSecretBasic.effective(None);
+        let confidential_client = ClientAuthMethod::ClientSecretBasic.effective(Some("secret"));
+
+        assert_eq!(public_client, ClientAuthMethod::None);
+        assert!(
+            metadata
+                .assert_supports_client_auth_method(&public_client)
+                .is_ok()
+        );
+        assert!(
+            metadata
+                .assert_supports_client_auth_method(&confidential_client)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn it_accepts_public_clients_when_the_idp_omits_none() {
+        let metadata = get_metadata(json!({
+            "token_endpoint_auth_methods_supported": ["client_secret_basic", "private_key_jwt"],
+        }));
+
+        assert!(
+            metadata
+                .assert_supports_client_auth_method(&ClientAuthMethod::None)
+                .is_ok()
+        );
+        assert!(
+            metadata
+                .assert_supports_client_auth_method(&ClientAuthMethod::ClientSecretPost)
+                .is_err()
+        );
+    }
 }
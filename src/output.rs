@@ -0,0 +1,13 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Output {
+    /// Access token. More: <https://www.rfc-editor.org/rfc/rfc6749#section-1.4>
+    AccessToken,
+    /// OpenID Connect ID token. Requires `openid` scope. More: <https://openid.net/specs/openid-connect-core-1_0.html#IDToken>
+    IdToken,
+    /// Refresh token. More: <https://www.rfc-editor.org/rfc/rfc6749#section-1.5>
+    RefreshToken,
+}
@@ -1,5 +1,5 @@
 use crate::args::Arguments;
-use crate::auth_browser::page::Page;
+use crate::auth_browser::authorization_page::AuthorizationPage;
 use crate::oauth_client::OAuthClient;
 use crate::token_info::TokenInfo;
 use anyhow::Result;
@@ -10,7 +10,7 @@ use super::token_retriever::TokenRetriever;
 
 pub struct AuthorizationCodeRetriever<'a> {
     oauth_client: &'a OAuthClient<'a>,
-    auth_page: Page,
+    auth_page: Box<dyn AuthorizationPage>,
     args: &'a Arguments,
 }
 
@@ -18,7 +18,7 @@ impl AuthorizationCodeRetriever<'_> {
     pub fn new<'b>(
         args: &'b Arguments,
         oauth_client: &'b OAuthClient<'b>,
-        auth_page: Page,
+        auth_page: Box<dyn AuthorizationPage>,
     ) -> AuthorizationCodeRetriever<'b> {
         AuthorizationCodeRetriever {
             oauth_client,
@@ -31,7 +31,7 @@ impl AuthorizationCodeRetriever<'_> {
 #[async_trait(?Send)]
 impl TokenRetriever for AuthorizationCodeRetriever<'_> {
     async fn retrieve(&mut self) -> Result<TokenInfo> {
-        let (url, csrf, _nonce) = self.oauth_client.authorize_url(None);
+        let (url, csrf, nonce) = self.oauth_client.authorize_url(None).await?;
 
         let code = self
             .auth_page
@@ -43,7 +43,7 @@ impl TokenRetriever for AuthorizationCodeRetriever<'_> {
             )
             .await?;
 
-        let token = self.oauth_client.exchange_code(&code, None).await?;
+        let token = self.oauth_client.exchange_code(&code, None, &nonce).await?;
 
         Ok(TokenInfo::from_token_response(token))
     }
@@ -1,5 +1,5 @@
 use crate::args::Arguments;
-use crate::auth_browser::page::Page;
+use crate::auth_browser::authorization_page::AuthorizationPage;
 use crate::oauth_client::OAuthClient;
 use crate::token_info::TokenInfo;
 use anyhow::Result;
@@ -11,7 +11,7 @@ use super::token_retriever::TokenRetriever;
 
 pub struct AuthorizationCodeWithPKCERetriever<'a> {
     oauth_client: &'a OAuthClient<'a>,
-    auth_page: Page,
+    auth_page: Box<dyn AuthorizationPage>,
     args: &'a Arguments,
 }
 
@@ -19,7 +19,7 @@ impl AuthorizationCodeWithPKCERetriever<'_> {
     pub fn new<'b>(
         args: &'b Arguments,
         oauth_client: &'b OAuthClient<'b>,
-        auth_page: Page,
+        auth_page: Box<dyn AuthorizationPage>,
     ) -> AuthorizationCodeWithPKCERetriever<'b> {
         AuthorizationCodeWithPKCERetriever {
             oauth_client,
@@ -34,7 +34,10 @@ impl TokenRetriever for AuthorizationCodeWithPKCERetriever<'_> {
     async fn retrieve(&mut self) -> Result<TokenInfo> {
         let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
-        let (url, csrf, _nonce) = self.oauth_client.authorize_url(Some(pkce_challenge));
+        let (url, csrf, nonce) = self
+            .oauth_client
+            .authorize_url(Some(pkce_challenge))
+            .await?;
 
         let code = self
             .auth_page
@@ -48,7 +51,7 @@ impl TokenRetriever for AuthorizationCodeWithPKCERetriever<'_> {
 
         let token = self
             .oauth_client
-            .exchange_code(&code, Some(pkce_verifier))
+            .exchange_code(&code, Some(pkce_verifier), &nonce)
             .await?;
 
         return Ok(TokenInfo::from_token_response(token));
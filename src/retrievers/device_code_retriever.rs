@@ -0,0 +1,24 @@
+use crate::{OAuthClient, token_info::TokenInfo};
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::token_retriever::TokenRetriever;
+
+pub struct DeviceCodeRetriever<'a> {
+    oauth_client: &'a OAuthClient<'a>,
+}
+
+impl DeviceCodeRetriever<'_> {
+    pub fn new<'b>(oauth_client: &'b OAuthClient<'b>) -> DeviceCodeRetriever<'b> {
+        DeviceCodeRetriever { oauth_client }
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenRetriever for DeviceCodeRetriever<'_> {
+    async fn retrieve(&mut self) -> Result<TokenInfo> {
+        Ok(TokenInfo::from_token_response(
+            self.oauth_client.exchange_device_code().await?,
+        ))
+    }
+}
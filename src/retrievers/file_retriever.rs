@@ -1,14 +1,21 @@
 use crate::FileState;
 use crate::args::Arguments;
 use crate::oauth_client::OAuthClient;
+use crate::output::Output;
 use crate::token_info::TokenInfo;
+use crate::token_key::{TokenKey, covers_scope};
 use anyhow::Result;
 use async_trait::async_trait;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
 use super::token_retriever::TokenRetriever;
 
+/// Whether the token expires within `remaining`. Tokens without a known expiry are expired
+fn expires_within(expires: Option<SystemTime>, remaining: Duration) -> bool {
+    expires.is_none_or(|expires| expires < SystemTime::now() + remaining)
+}
+
 #[derive(Error, Debug)]
 enum FileRetrieverError {
     #[error("Token not found in state file")]
@@ -19,6 +26,7 @@ pub struct FileRetriever<'a> {
     oauth_client: &'a OAuthClient<'a>,
     file_state: &'a mut FileState,
     args: &'a Arguments,
+    key: TokenKey,
 }
 
 impl FileRetriever<'_> {
@@ -31,65 +39,240 @@ impl FileRetriever<'_> {
             oauth_client,
             file_state,
             args,
+            key: TokenKey::from(args),
+        }
+    }
+
+    /// Whether the token can be used with the current client certificate and `--dpop`
+    fn is_usable(&self, token_info: &TokenInfo) -> bool {
+        // NOTE: Tokens bound to another certificate are useless with the current one
+        if token_info.cert_thumbprint != self.oauth_client.certificate_thumbprint() {
+            log::debug!("Token is bound to a different client certificate");
+            return false;
+        }
+
+        // NOTE: Bearer tokens aren't accepted while using DPoP and vice versa
+        if token_info.is_dpop_bound() != self.args.dpop {
+            log::debug!("Token binding doesn't match `--dpop`");
+            return false;
         }
+
+        true
     }
 
-    async fn refresh_token(&mut self, refresh_token: &str) -> Result<TokenInfo> {
+    fn is_expired(&self, expires: Option<SystemTime>) -> bool {
+        // NOTE: Tokens are refreshed early, so they outlive the caller's use and the clock
+        // difference with the IdP
+        let remaining = Duration::from_secs(self.args.min_ttl + self.args.clock_skew);
+
+        expires_within(expires, remaining)
+    }
+
+    /// ID token is only checked when it's printed, as it expires on its own
+    fn is_id_token_expired(&self, token_info: &TokenInfo) -> bool {
+        self.args.output == Output::IdToken && self.is_expired(token_info.id_token_expires())
+    }
+
+    async fn refresh_token(
+        &mut self,
+        previous: TokenInfo,
+        refresh_token: &str,
+    ) -> Result<TokenInfo> {
         let result = self
             .oauth_client
-            .refresh_token(refresh_token.to_owned())
+            .refresh_token(refresh_token.to_owned(), None)
             .await;
 
         match result {
             Ok(token_response) => {
-                let token_info = TokenInfo::from_token_response(token_response);
+                let previous_id_token = match self.is_expired(previous.id_token_expires()) {
+                    true => None,
+                    false => previous.id_token,
+                };
+                let mut token_info = TokenInfo::from_token_response(token_response)
+                    .with_default_lifetime(self.args.default_token_lifetime);
+                // NOTE: Servers may skip tokens that haven't changed in the refresh response
+                token_info.refresh_token = token_info.refresh_token.or(previous.refresh_token);
+                token_info.id_token = token_info.id_token.or(previous_id_token);
+                token_info.scope = token_info.scope.or(previous.scope);
+                token_info.cert_thumbprint = self.oauth_client.certificate_thumbprint();
 
                 self.file_state
-                    .upsert_token_info(self.args.client_id.to_owned(), token_info.to_owned())?;
+                    .upsert_token_info(&self.key, token_info.to_owned())?;
 
                 Ok(token_info)
             }
             Err(_) => {
-                self.file_state
-                    .clear_token_info(self.args.client_id.to_owned())?;
+                self.file_state.clear_token_info(&self.key)?;
 
                 Err(FileRetrieverError::TokenInfoNotFound.into())
             }
         }
     }
-}
 
-#[async_trait(?Send)]
-impl TokenRetriever for FileRetriever<'_> {
-    async fn retrieve(&mut self) -> Result<TokenInfo> {
-        let token_info = self.file_state.read_token_info(&self.args.client_id);
+    /// Cached token granted to more scopes than requested. Its refresh token is exchanged for
+    /// a token narrowed down to the requested ones.
+    fn read_covering_token_info(&mut self) -> Option<(TokenKey, TokenInfo)> {
+        self.file_state
+            .read_sibling_token_info(&self.key)
+            .into_iter()
+            .find(|(key, token_info)| {
+                token_info.refresh_token.is_some()
+                    && covers_scope(token_info.granted_scope(key), self.key.scope())
+                    && self.is_usable(token_info)
+            })
+    }
 
-        if token_info.is_none() {
-            return Err(FileRetrieverError::TokenInfoNotFound.into());
+    /// Refreshes the covering token with narrower scope. More: <https://www.rfc-editor.org/rfc/rfc6749#section-6>
+    async fn narrow_token(
+        &mut self,
+        covering_key: TokenKey,
+        mut covering: TokenInfo,
+    ) -> Result<TokenInfo> {
+        log::debug!(
+            "Narrowing down token requested with `{}`",
+            covering_key.scope()
+        );
+
+        let refresh_token = covering.refresh_token.to_owned().unwrap_or_default();
+        let token_response = self
+            .oauth_client
+            .refresh_token(refresh_token, Some(self.key.scope()))
+            .await
+            .map_err(|_| FileRetrieverError::TokenInfoNotFound)?;
+
+        let mut token_info = TokenInfo::from_token_response(token_response)
+            .with_default_lifetime(self.args.default_token_lifetime);
+        token_info.scope = token_info.scope.or(Some(self.key.scope().to_owned()));
+        token_info.cert_thumbprint = self.oauth_client.certificate_thumbprint();
+
+        // NOTE: Rotated refresh token keeps the originally granted scope, so the covering token
+        // is updated with it
+        if let Some(rotated) = token_info.refresh_token.to_owned() {
+            covering.refresh_token = Some(rotated);
+            self.file_state.upsert_token_info(&covering_key, covering)?;
         }
 
-        let token_info = token_info.unwrap();
+        // NOTE: Narrowed token is stored without the refresh token, so once it expires, it's
+        // narrowed again from the covering one instead of refreshed to the full scope
+        self.file_state.upsert_token_info(
+            &self.key,
+            TokenInfo {
+                refresh_token: None,
+                ..token_info.to_owned()
+            },
+        )?;
 
-        let expires = token_info.expires.unwrap_or_else(SystemTime::now);
+        Ok(token_info)
+    }
 
-        let is_token_expired = expires < SystemTime::now();
+    async fn retrieve_stored(&mut self) -> Result<TokenInfo> {
+        let Some(token_info) = self.file_state.read_token_info(&self.key) else {
+            return self.retrieve_covered().await;
+        };
 
-        if !is_token_expired {
+        if !self.is_usable(&token_info) {
+            return Err(FileRetrieverError::TokenInfoNotFound.into());
+        }
+
+        if !self.is_expired(token_info.expires) && !self.is_id_token_expired(&token_info) {
             return Ok(token_info);
         }
 
-        match token_info.refresh_token {
+        match token_info.refresh_token.to_owned() {
             Some(token) => {
-                let token_info = self.refresh_token(&token).await?;
+                let token_info = self.refresh_token(token_info, &token).await?;
 
                 Ok(token_info)
             }
             None => {
-                self.file_state
-                    .clear_token_info(self.args.client_id.to_owned())?;
+                self.file_state.clear_token_info(&self.key)?;
 
-                Err(FileRetrieverError::TokenInfoNotFound.into())
+                self.retrieve_covered().await
             }
         }
     }
+
+    async fn retrieve_covered(&mut self) -> Result<TokenInfo> {
+        match self.read_covering_token_info() {
+            Some((covering_key, covering)) => self.narrow_token(covering_key, covering).await,
+            None => Err(FileRetrieverError::TokenInfoNotFound.into()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenRetriever for FileRetriever<'_> {
+    async fn retrieve(&mut self) -> Result<TokenInfo> {
+        let token_info = self.retrieve_stored().await?;
+
+        // NOTE: Refresh responses don't have to contain a new ID token, so the grant is run again
+        if self.is_id_token_expired(&token_info) {
+            log::debug!("ID token has expired");
+            return Err(FileRetrieverError::TokenInfoNotFound.into());
+        }
+
+        Ok(token_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use std::path::PathBuf;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn get_tmp_path() -> Result<(TempDir, PathBuf)> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut path = tmp_dir.path().to_owned();
+        path.push(".doken.json");
+        Ok((tmp_dir, path))
+    }
+
+    fn expires_in(seconds: u64) -> Option<SystemTime> {
+        Some(SystemTime::now() + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn it_treats_tokens_without_expiry_as_expired() {
+        assert!(expires_within(None, Duration::ZERO));
+        assert!(!expires_within(expires_in(60), Duration::ZERO));
+    }
+
+    #[test]
+    fn it_treats_tokens_expiring_within_min_ttl_and_clock_skew_as_expired() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path).unwrap();
+        let args = Arguments {
+            min_ttl: 60,
+            clock_skew: 30,
+            ..Default::default()
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+        let file_retriever = FileRetriever::new(&args, &oauth_client, &mut file_state);
+
+        assert!(file_retriever.is_expired(expires_in(30)));
+        assert!(file_retriever.is_expired(expires_in(80)));
+        assert!(!file_retriever.is_expired(expires_in(120)));
+    }
+
+    #[test]
+    fn it_refreshes_tokens_by_clock_skew_only_without_min_ttl() {
+        let (_tmp_dir, tmp_path) = get_tmp_path().unwrap();
+        let mut file_state = FileState::_from(tmp_path).unwrap();
+        let args = Arguments {
+            min_ttl: 0,
+            clock_skew: 30,
+            ..Default::default()
+        };
+        let oauth_client = OAuthClient::new(&args, None).unwrap();
+        let file_retriever = FileRetriever::new(&args, &oauth_client, &mut file_state);
+
+        assert!(file_retriever.is_expired(expires_in(20)));
+        assert!(!file_retriever.is_expired(expires_in(40)));
+    }
 }
@@ -1,6 +1,6 @@
 use crate::OAuthClient;
 use crate::args::Arguments;
-use crate::auth_browser::page::Page;
+use crate::auth_browser::authorization_page::AuthorizationPage;
 use crate::token_info::TokenInfo;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -11,14 +11,14 @@ use super::token_retriever::TokenRetriever;
 pub struct ImplicitRetriever<'a> {
     args: &'a Arguments,
     oauth_client: &'a OAuthClient<'a>,
-    auth_page: Page,
+    auth_page: Box<dyn AuthorizationPage>,
 }
 
 impl ImplicitRetriever<'_> {
     pub fn new<'b>(
         args: &'b Arguments,
         oauth_client: &'b OAuthClient<'b>,
-        auth_page: Page,
+        auth_page: Box<dyn AuthorizationPage>,
     ) -> ImplicitRetriever<'b> {
         ImplicitRetriever {
             args,
@@ -31,7 +31,7 @@ impl ImplicitRetriever<'_> {
 #[async_trait(?Send)]
 impl TokenRetriever for ImplicitRetriever<'_> {
     async fn retrieve(&mut self) -> Result<TokenInfo> {
-        let (url, csrf) = self.oauth_client.implicit_url();
+        let (url, csrf) = self.oauth_client.implicit_url().await?;
 
         self.auth_page
             .get_token_data(
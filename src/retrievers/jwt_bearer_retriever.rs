@@ -0,0 +1,24 @@
+use crate::{OAuthClient, token_info::TokenInfo};
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::token_retriever::TokenRetriever;
+
+pub struct JwtBearerRetriever<'a> {
+    oauth_client: &'a OAuthClient<'a>,
+}
+
+impl JwtBearerRetriever<'_> {
+    pub fn new<'b>(oauth_client: &'b OAuthClient<'b>) -> JwtBearerRetriever<'b> {
+        JwtBearerRetriever { oauth_client }
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenRetriever for JwtBearerRetriever<'_> {
+    async fn retrieve(&mut self) -> Result<TokenInfo> {
+        Ok(TokenInfo::from_token_response(
+            self.oauth_client.exchange_jwt_bearer().await?,
+        ))
+    }
+}
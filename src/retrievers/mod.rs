@@ -1,7 +1,10 @@
 pub mod authorization_code_retriever;
 pub mod authorization_code_with_pkce_retriever;
 pub mod client_credentials_retriever;
+pub mod device_code_retriever;
 pub mod file_retriever;
 pub mod implicit_retriever;
+pub mod jwt_bearer_retriever;
 pub mod resource_owner_password_client_credentials_retriever;
+pub mod token_exchange_retriever;
 pub mod token_retriever;
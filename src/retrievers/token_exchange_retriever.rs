@@ -0,0 +1,33 @@
+use crate::{OAuthClient, token_info::TokenInfo};
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::token_retriever::TokenRetriever;
+
+pub struct TokenExchangeRetriever<'a> {
+    oauth_client: &'a OAuthClient<'a>,
+    subject_token: String,
+}
+
+impl TokenExchangeRetriever<'_> {
+    pub fn new<'b>(
+        oauth_client: &'b OAuthClient<'b>,
+        subject_token: String,
+    ) -> TokenExchangeRetriever<'b> {
+        TokenExchangeRetriever {
+            oauth_client,
+            subject_token,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenRetriever for TokenExchangeRetriever<'_> {
+    async fn retrieve(&mut self) -> Result<TokenInfo> {
+        Ok(TokenInfo::from_token_response(
+            self.oauth_client
+                .exchange_token(&self.subject_token)
+                .await?,
+        ))
+    }
+}
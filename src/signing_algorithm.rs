@@ -0,0 +1,22 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Debug)]
+pub enum SigningAlgorithm {
+    /// RSASSA-PKCS1-v1_5 using SHA-256. More: <https://www.rfc-editor.org/rfc/rfc7518#section-3.3>
+    #[serde(rename = "RS256")]
+    #[value(name = "RS256")]
+    Rs256,
+    /// ECDSA using P-256 and SHA-256. More: <https://www.rfc-editor.org/rfc/rfc7518#section-3.4>
+    #[serde(rename = "ES256")]
+    #[value(name = "ES256")]
+    Es256,
+    /// RSASSA-PSS using SHA-256. More: <https://www.rfc-editor.org/rfc/rfc7518#section-3.5>
+    #[serde(rename = "PS256")]
+    #[value(name = "PS256")]
+    Ps256,
+    /// HMAC using SHA-256. More: <https://www.rfc-editor.org/rfc/rfc7518#section-3.2>
+    #[serde(rename = "HS256")]
+    #[value(name = "HS256")]
+    Hs256,
+}
@@ -0,0 +1,72 @@
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Path of a test fixture in `tests/data`
+pub fn test_data(file: &str) -> String {
+    format!("{}/tests/data/{file}", env!("CARGO_MANIFEST_DIR"))
+}
+
+async fn read_request(stream: &mut TcpStream) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    let mut content_length = 0;
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        request.push_str(&line);
+
+        if line.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap();
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await.unwrap();
+    request.push_str(&String::from_utf8(body).unwrap());
+
+    request
+}
+
+/// Local server answering unit tests' requests with the responses in the given order, one per
+/// connection. Returns its url and the received requests, once all of the responses are sent
+pub async fn serve(responses: Vec<(u16, String)>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let requests = tokio::spawn(async move {
+        let mut requests = vec![];
+
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            requests.push(read_request(&mut stream).await);
+
+            let response = format!(
+                "HTTP/1.1 {status} Test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+        }
+
+        requests
+    });
+
+    (url, requests)
+}
+
+/// Value of the form parameter sent in the request
+pub fn form_param(request: &str, name: &str) -> Option<String> {
+    let (_, body) = request.split_once("\r\n\r\n")?;
+
+    form_urlencoded::parse(body.as_bytes())
+        .find(|(param, _)| param == name)
+        .map(|(_, value)| value.into_owned())
+}
@@ -1,8 +1,10 @@
+use crate::jwt;
+use crate::oauth_client::DokenTokenResponse;
+use crate::token_key::TokenKey;
 use oauth2::TokenResponse;
-use oauth2::basic::BasicTokenResponse;
 use serde::{Deserialize, Serialize};
 use std::ops::Add;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct TokenInfo {
@@ -10,24 +12,188 @@ pub struct TokenInfo {
 
     pub refresh_token: Option<String>,
 
+    /// OpenID Connect ID token. More: <https://openid.net/specs/openid-connect-core-1_0.html#IDToken>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+
     pub expires: Option<SystemTime>,
 
     pub scope: Option<String>,
+
+    /// Token type given by the server ex. `Bearer` or `DPoP`. More: <https://www.rfc-editor.org/rfc/rfc6749#section-7.1>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+
+    /// Thumbprint of the client certificate the token is bound to. More: <https://www.rfc-editor.org/rfc/rfc8705#section-3>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_thumbprint: Option<String>,
 }
 
 impl TokenInfo {
-    pub fn from_token_response(response: BasicTokenResponse) -> TokenInfo {
+    pub fn from_token_response(response: DokenTokenResponse) -> TokenInfo {
+        let access_token = response.access_token().secret().to_owned();
+        // NOTE: `expires_in` is only recommended, so many IdPs skip it. Lifetime of JWT access
+        // tokens is still known from their claims
+        let expires = response
+            .expires_in()
+            .map(|duration| SystemTime::now().add(duration))
+            .or_else(|| jwt::expires(&access_token));
+
         TokenInfo {
-            access_token: response.access_token().secret().to_owned(),
+            access_token,
             refresh_token: response
                 .refresh_token()
                 .map(|token| token.secret().to_owned()),
-            expires: response
-                .expires_in()
-                .map(|duration| SystemTime::now().add(duration)),
-            scope: response
-                .scopes()
-                .map(|v| v.iter().map(|scope| scope.to_string()).collect()),
+            id_token: response.extra_fields().id_token.to_owned(),
+            expires,
+            scope: response.scopes().map(|scopes| {
+                scopes
+                    .iter()
+                    .map(|scope| scope.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }),
+            token_type: Some(response.token_type().as_ref().to_owned()),
+            cert_thumbprint: None,
         }
     }
+
+    /// Sets the expiry of tokens the IdP gave no expiry for. Zero lifetime keeps them expired
+    pub fn with_default_lifetime(mut self, lifetime: u64) -> TokenInfo {
+        if self.expires.is_none() && lifetime > 0 {
+            self.expires = Some(SystemTime::now() + Duration::from_secs(lifetime));
+        }
+
+        self
+    }
+
+    /// Time given by `exp` claim of the ID token, which expires independently of the access token
+    pub fn id_token_expires(&self) -> Option<SystemTime> {
+        jwt::expires(self.id_token.as_deref()?)
+    }
+
+    /// Scopes granted to the token. Servers skip them in responses when they match the requested
+    /// ones. More: <https://www.rfc-editor.org/rfc/rfc6749#section-5.1>
+    pub fn granted_scope<'a>(&'a self, key: &'a TokenKey) -> &'a str {
+        self.scope.as_deref().unwrap_or(key.scope())
+    }
+
+    /// Whether the token is bound to a DPoP key. More: <https://www.rfc-editor.org/rfc/rfc9449#section-5>
+    pub fn is_dpop_bound(&self) -> bool {
+        self.token_type
+            .as_deref()
+            .is_some_and(|token_type| token_type.eq_ignore_ascii_case("dpop"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use base64::Engine;
+    use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+    use serde_json::json;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn get_jwt(claims: serde_json::Value) -> String {
+        let encode = |part: serde_json::Value| BASE64_URL_SAFE_NO_PAD.encode(part.to_string());
+
+        format!(
+            "{}.{}.signature",
+            encode(json!({ "alg": "RS256", "typ": "JWT" })),
+            encode(claims)
+        )
+    }
+
+    fn get_token_response(response: serde_json::Value) -> DokenTokenResponse {
+        serde_json::from_value(response).unwrap()
+    }
+
+    #[test]
+    fn it_takes_expiry_from_expires_in() {
+        let token_info = TokenInfo::from_token_response(get_token_response(json!({
+            "access_token": get_jwt(json!({ "exp": 1 })),
+            "token_type": "bearer",
+            "expires_in": 300,
+        })));
+
+        let expires = token_info.expires.unwrap();
+
+        assert!(expires > SystemTime::now() + Duration::from_secs(290));
+        assert!(expires <= SystemTime::now() + Duration::from_secs(300));
+    }
+
+    #[test]
+    fn it_takes_expiry_from_jwt_without_expires_in() {
+        let token_info = TokenInfo::from_token_response(get_token_response(json!({
+            "access_token": get_jwt(json!({ "sub": "test-user", "exp": 2000000000 })),
+            "token_type": "bearer",
+        })));
+
+        assert_eq!(
+            token_info.expires,
+            Some(UNIX_EPOCH + Duration::from_secs(2000000000))
+        );
+    }
+
+    #[test]
+    fn it_leaves_opaque_token_without_expiry() {
+        let opaque = TokenInfo::from_token_response(get_token_response(json!({
+            "access_token": "test-opaque-access-token",
+            "token_type": "bearer",
+        })));
+        let without_exp = TokenInfo::from_token_response(get_token_response(json!({
+            "access_token": get_jwt(json!({ "sub": "test-user" })),
+            "token_type": "bearer",
+        })));
+
+        assert_eq!(opaque.expires, None);
+        assert_eq!(without_exp.expires, None);
+    }
+
+    #[test]
+    fn it_falls_back_to_default_lifetime() {
+        let get_opaque = || {
+            TokenInfo::from_token_response(get_token_response(json!({
+                "access_token": "test-opaque-access-token",
+                "token_type": "bearer",
+            })))
+        };
+
+        let expires = get_opaque().with_default_lifetime(300).expires.unwrap();
+
+        assert!(expires > SystemTime::now() + Duration::from_secs(290));
+        assert_eq!(get_opaque().with_default_lifetime(0).expires, None);
+    }
+
+    #[test]
+    fn it_keeps_expiry_given_by_the_idp_over_default_lifetime() {
+        let token_info = TokenInfo::from_token_response(get_token_response(json!({
+            "access_token": get_jwt(json!({ "exp": 2000000000 })),
+            "token_type": "bearer",
+        })))
+        .with_default_lifetime(300);
+
+        assert_eq!(
+            token_info.expires,
+            Some(UNIX_EPOCH + Duration::from_secs(2000000000))
+        );
+    }
+
+    #[test]
+    fn it_takes_id_token_expiry_from_its_own_claims() {
+        let token_info = TokenInfo::from_token_response(get_token_response(json!({
+            "access_token": "test-opaque-access-token",
+            "token_type": "bearer",
+            "expires_in": 300,
+            "id_token": get_jwt(json!({ "exp": 1 })),
+        })));
+
+        assert_eq!(
+            token_info.id_token_expires(),
+            Some(UNIX_EPOCH + Duration::from_secs(1))
+        );
+    }
 }
@@ -0,0 +1,269 @@
+use crate::args::{Arguments, DEFAULT_SCOPE};
+use crate::grant::Grant;
+use anyhow::bail;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use serde_variant::to_variant_name;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies a token in the state. Tokens are never shared between requests differing
+/// in any of these values
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TokenKey {
+    issuer: Option<String>,
+    client_id: String,
+    grant: Option<String>,
+    scope: Option<String>,
+    audience: Option<String>,
+    username: Option<String>,
+    subject_token: Option<String>,
+    subject_token_profile: Option<String>,
+    actor_token: Option<String>,
+    requested_token_type: Option<String>,
+    resource: Option<String>,
+}
+
+/// Hash identifying a token without keeping it in the key
+fn fingerprint(token: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Sorts and deduplicates scopes, so the same set of them always gives the same key
+pub fn normalize_scope(scope: &str) -> String {
+    scope
+        .split_whitespace()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether every requested scope is one of the granted ones
+pub fn covers_scope(granted: &str, requested: &str) -> bool {
+    let granted: BTreeSet<_> = granted.split_whitespace().collect();
+
+    requested
+        .split_whitespace()
+        .all(|scope| granted.contains(scope))
+}
+
+impl TokenKey {
+    pub fn new(
+        issuer: Option<&str>,
+        client_id: &str,
+        grant: &Grant,
+        scope: &str,
+        audience: Option<&str>,
+        username: Option<&str>,
+    ) -> TokenKey {
+        TokenKey {
+            issuer: Some(issuer.unwrap_or_default().to_owned()),
+            client_id: client_id.to_owned(),
+            grant: Some(to_variant_name(grant).unwrap().to_owned()),
+            scope: Some(normalize_scope(scope)),
+            audience: audience.map(|audience| audience.to_owned()),
+            username: username.map(|username| username.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Key of a token cached by version 1 of the state, which kept only client_id. Scope is the one
+    /// given with the token, and the default one when it's unknown
+    pub fn from_version_1(client_id: &str, scope: Option<&str>) -> TokenKey {
+        TokenKey {
+            client_id: client_id.to_owned(),
+            scope: Some(normalize_scope(scope.unwrap_or(DEFAULT_SCOPE))),
+            ..Default::default()
+        }
+    }
+
+    /// Key a token of the same request got while migrating it from version 1 of the state
+    pub fn to_version_1(&self) -> TokenKey {
+        TokenKey {
+            issuer: None,
+            grant: None,
+            ..self.to_owned()
+        }
+    }
+
+    pub fn scope(&self) -> &str {
+        self.scope.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the keys differ in scope only
+    pub fn is_sibling_of(&self, other: &TokenKey) -> bool {
+        let without_scope = |key: &TokenKey| TokenKey {
+            scope: None,
+            ..key.to_owned()
+        };
+
+        without_scope(self) == without_scope(other)
+    }
+
+    /// Whether the token was issued to the same client by the same issuer
+    pub fn is_of_client(&self, client: &TokenKey) -> bool {
+        self.client_id == client.client_id && self.issuer == client.issuer
+    }
+}
+
+impl FromStr for TokenKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = TokenKey::default();
+
+        for (name, value) in form_urlencoded::parse(s.as_bytes()).into_owned() {
+            match name.as_str() {
+                "issuer" => key.issuer = Some(value),
+                "client_id" => key.client_id = value,
+                "grant" => key.grant = Some(value),
+                "scope" => key.scope = Some(value),
+                "audience" => key.audience = Some(value),
+                "username" => key.username = Some(value),
+                "subject_token" => key.subject_token = Some(value),
+                "subject_token_profile" => key.subject_token_profile = Some(value),
+                "actor_token" => key.actor_token = Some(value),
+                "requested_token_type" => key.requested_token_type = Some(value),
+                "resource" => key.resource = Some(value),
+                _ => bail!("Unknown `{name}` part of the state key"),
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+impl From<&Arguments> for TokenKey {
+    fn from(args: &Arguments) -> Self {
+        // NOTE: Issuer of discovery is known only after a network call
+        let issuer = args
+            .issuer
+            .as_deref()
+            .or(args.discovery_url.as_deref())
+            .or(args.token_url.as_deref())
+            .or(args.authorization_url.as_deref());
+
+        let key = TokenKey::new(
+            issuer,
+            &args.client_id,
+            &args.grant,
+            &args.scope,
+            args.audience.as_deref(),
+            args.username.as_deref(),
+        );
+
+        if !matches!(args.grant, Grant::TokenExchange) {
+            return key;
+        }
+
+        // NOTE: Exchanged tokens depend on the inputs of the exchange too. Given tokens are
+        // hashed, so they aren't kept in the state in plain text
+        TokenKey {
+            subject_token: args.subject_token.as_deref().map(fingerprint),
+            subject_token_profile: args.subject_token_profile.to_owned(),
+            actor_token: args.actor_token.as_deref().map(fingerprint),
+            requested_token_type: args.requested_token_type.to_owned(),
+            resource: args.resource.to_owned(),
+            ..key
+        }
+    }
+}
+
+impl fmt::Display for TokenKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+
+        if let Some(issuer) = &self.issuer {
+            serializer.append_pair("issuer", issuer);
+        }
+
+        serializer.append_pair("client_id", &self.client_id);
+
+        if let Some(grant) = &self.grant {
+            serializer.append_pair("grant", grant);
+        }
+
+        if let Some(scope) = &self.scope {
+            serializer.append_pair("scope", scope);
+        }
+
+        if let Some(audience) = &self.audience {
+            serializer.append_pair("audience", audience);
+        }
+
+        if let Some(username) = &self.username {
+            serializer.append_pair("username", username);
+        }
+
+        if let Some(subject_token) = &self.subject_token {
+            serializer.append_pair("subject_token", subject_token);
+        }
+
+        if let Some(subject_token_profile) = &self.subject_token_profile {
+            serializer.append_pair("subject_token_profile", subject_token_profile);
+        }
+
+        if let Some(actor_token) = &self.actor_token {
+            serializer.append_pair("actor_token", actor_token);
+        }
+
+        if let Some(requested_token_type) = &self.requested_token_type {
+            serializer.append_pair("requested_token_type", requested_token_type);
+        }
+
+        if let Some(resource) = &self.resource {
+            serializer.append_pair("resource", resource);
+        }
+
+        write!(f, "{}", serializer.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![deny(warnings)]
+
+    use super::*;
+
+    fn get_token_exchange_args() -> Arguments {
+        Arguments {
+            client_id: "test-client-id".to_owned(),
+            grant: Grant::TokenExchange,
+            subject_token: Some("test-subject-token".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_keys_exchanged_tokens_by_exchange_inputs() {
+        let args = get_token_exchange_args();
+        let other_resource_args = Arguments {
+            resource: Some("https://my-api.com".to_owned()),
+            ..get_token_exchange_args()
+        };
+        let other_subject_args = Arguments {
+            subject_token: Some("other-subject-token".to_owned()),
+            ..get_token_exchange_args()
+        };
+
+        let key = TokenKey::from(&args);
+
+        assert_ne!(key, TokenKey::from(&other_resource_args));
+        assert_ne!(key, TokenKey::from(&other_subject_args));
+        assert!(!key.to_string().contains("test-subject-token"));
+        assert_eq!(key.to_string().parse::<TokenKey>().unwrap(), key);
+    }
+
+    #[test]
+    fn it_ignores_exchange_inputs_of_other_grants() {
+        let args = Arguments {
+            grant: Grant::ClientCredentials,
+            ..get_token_exchange_args()
+        };
+
+        assert!(!TokenKey::from(&args).to_string().contains("subject_token"));
+    }
+}
@@ -77,6 +77,11 @@ impl Image for Keycloak {
     where
         Self: Sized,
     {
-        vec!["start-dev".to_owned()].into_iter()
+        // NOTE: Token Exchange is a preview feature of Keycloak
+        vec![
+            "start-dev".to_owned(),
+            "--features=token-exchange,admin-fine-grained-authz".to_owned(),
+        ]
+        .into_iter()
     }
 }
@@ -44,7 +44,7 @@ impl KeycloakClient {
         realm_name: &str,
         username: &str,
         password: &str,
-        clients: &[(String, String, bool)],
+        clients: &[(String, String, bool, String)],
     ) -> Result<()> {
         self.inner
             .post(RealmRepresentation {
@@ -65,10 +65,18 @@ impl KeycloakClient {
                     clients
                         .iter()
                         .map(
-                            |(client_id, redirect_uri, public_client)| ClientRepresentation {
+                            |(
+                                client_id,
+                                redirect_uri,
+                                public_client,
+                                client_authenticator_type,
+                            )| ClientRepresentation {
                                 id: Some(client_id.to_owned()),
                                 enabled: Some(true),
                                 public_client: Some(*public_client),
+                                client_authenticator_type: Some(
+                                    client_authenticator_type.to_owned(),
+                                ),
                                 implicit_flow_enabled: Some(true),
                                 direct_access_grants_enabled: Some(true),
                                 standard_flow_enabled: Some(true),
@@ -2,7 +2,10 @@ use common::keycloak_client::ACCESS_TOKEN_LIFESPAN;
 use common::{assert_token_like, remove_config_if_available};
 use std::time::Duration;
 
-use doken::{args::Arguments, auth_browser::browser::Browser, get_token, grant::Grant};
+use doken::{
+    args::Arguments, auth_browser::browser::Browser, client_auth_method::ClientAuthMethod,
+    get_token, get_userinfo, grant::Grant, introspect, revoke,
+};
 use lazy_static::lazy_static;
 use serial_test::serial;
 use std::sync::Arc;
@@ -89,10 +92,33 @@ async fn get_idp_info() -> &'static IdentityProviderInfo {
                 "https://wykop.pl/this/is/test/string/that/should/be/checked";
             const CLIENT_ID_3: &str = "test-client-id3";
             const REDIRECT_URI_3: &str = "https://localhost:1234/test/callback";
+            const CLIENT_ID_4: &str = "test-client-id4";
+            const REDIRECT_URI_4: &str = "http://localhost:3000/jwt/callback";
             let clients = vec![
-                (CLIENT_ID_1.to_owned(), REDIRECT_URI_1.to_owned(), false),
-                (CLIENT_ID_2.to_owned(), REDIRECT_URI_2.to_owned(), false),
-                (CLIENT_ID_3.to_owned(), REDIRECT_URI_3.to_owned(), true),
+                (
+                    CLIENT_ID_1.to_owned(),
+                    REDIRECT_URI_1.to_owned(),
+                    false,
+                    "client-secret".to_owned(),
+                ),
+                (
+                    CLIENT_ID_2.to_owned(),
+                    REDIRECT_URI_2.to_owned(),
+                    false,
+                    "client-secret".to_owned(),
+                ),
+                (
+                    CLIENT_ID_3.to_owned(),
+                    REDIRECT_URI_3.to_owned(),
+                    true,
+                    "client-secret".to_owned(),
+                ),
+                (
+                    CLIENT_ID_4.to_owned(),
+                    REDIRECT_URI_4.to_owned(),
+                    false,
+                    "client-secret-jwt".to_owned(),
+                ),
             ];
             kc.create_realm(REALM_NAME, USERNAME, PASSWORD, &clients)
                 .await
@@ -102,6 +128,7 @@ async fn get_idp_info() -> &'static IdentityProviderInfo {
             let authorize_url = kc.authorize_url(REALM_NAME);
             let client_secret_1 = kc.get_client_secret(REALM_NAME, CLIENT_ID_1).await.unwrap();
             let client_secret_2 = kc.get_client_secret(REALM_NAME, CLIENT_ID_2).await.unwrap();
+            let client_secret_4 = kc.get_client_secret(REALM_NAME, CLIENT_ID_4).await.unwrap();
             IdentityProviderInfo {
                 _client: kc,
                 clients: vec![
@@ -120,6 +147,11 @@ async fn get_idp_info() -> &'static IdentityProviderInfo {
                         client_secret: String::new(),
                         redirect_uri: REDIRECT_URI_3.to_owned(),
                     },
+                    ClientInfo {
+                        client_id: CLIENT_ID_4.to_owned(),
+                        client_secret: client_secret_4,
+                        redirect_uri: REDIRECT_URI_4.to_owned(),
+                    },
                 ],
                 discovery_url,
                 _token_url: token_url,
@@ -451,6 +483,37 @@ fn it_authenticates_with_client_credentials_grant() {
     });
 }
 
+#[test]
+#[serial]
+fn it_authenticates_with_client_secret_jwt() {
+    let _ = env_logger::try_init();
+    TOKIO_RUNTIME.block_on(async {
+        let idp_info = get_idp_info().await;
+
+        let browser = AUTH_BROWSER.clone();
+        let browser = browser.lock().await;
+        remove_config_if_available();
+        let client_info = idp_info.clients.get(3).unwrap();
+        let token = get_token(
+            Arguments {
+                grant: Grant::ClientCredentials,
+                discovery_url: Some(idp_info.discovery_url.to_owned()),
+                client_id: client_info.client_id.to_owned(),
+                client_secret: Some(client_info.client_secret.to_owned()),
+                client_auth_method: ClientAuthMethod::ClientSecretJwt,
+                timeout: TIMEOUT,
+                scope: "email".to_owned(),
+                ..Default::default()
+            },
+            browser,
+        )
+        .await
+        .unwrap();
+
+        assert_token_like(token);
+    });
+}
+
 #[test]
 #[serial]
 fn it_authenticates_with_resource_owner_password_client_credentials_grant() {
@@ -482,3 +545,158 @@ fn it_authenticates_with_resource_owner_password_client_credentials_grant() {
         assert_token_like(pkce_token);
     });
 }
+
+#[test]
+#[serial]
+fn it_authenticates_with_token_exchange_grant() {
+    let _ = env_logger::try_init();
+    TOKIO_RUNTIME.block_on(async {
+        let idp_info = get_idp_info().await;
+
+        let browser = AUTH_BROWSER.clone();
+        let browser_lock = browser.lock().await;
+        remove_config_if_available();
+        let client_info = idp_info.clients.first().unwrap();
+        let subject_token = get_token(
+            Arguments {
+                grant: Grant::ResourceOwnerPasswordClientCredentials,
+                discovery_url: Some(idp_info.discovery_url.to_owned()),
+                client_id: client_info.client_id.to_owned(),
+                client_secret: Some(client_info.client_secret.to_owned()),
+                username: Some(USERNAME.to_owned()),
+                password: Some(PASSWORD.to_owned()),
+                scope: "email".to_owned(),
+                timeout: TIMEOUT,
+                ..Default::default()
+            },
+            browser_lock,
+        )
+        .await
+        .unwrap();
+
+        let browser_lock = browser.lock().await;
+        let exchanged_token = get_token(
+            Arguments {
+                grant: Grant::TokenExchange,
+                discovery_url: Some(idp_info.discovery_url.to_owned()),
+                client_id: client_info.client_id.to_owned(),
+                client_secret: Some(client_info.client_secret.to_owned()),
+                subject_token: Some(subject_token.to_owned()),
+                scope: "email".to_owned(),
+                timeout: TIMEOUT,
+                ..Default::default()
+            },
+            browser_lock,
+        )
+        .await
+        .unwrap();
+
+        assert_token_like(exchanged_token.to_owned());
+        assert_ne!(subject_token, exchanged_token);
+    });
+}
+
+#[test]
+#[serial]
+fn it_introspects_the_cached_token() {
+    let _ = env_logger::try_init();
+    TOKIO_RUNTIME.block_on(async {
+        let idp_info = get_idp_info().await;
+
+        let browser = AUTH_BROWSER.clone();
+        let browser = browser.lock().await;
+        remove_config_if_available();
+        let client_info = idp_info.clients.get(1).unwrap();
+        let args = Arguments {
+            grant: Grant::ClientCredentials,
+            discovery_url: Some(idp_info.discovery_url.to_owned()),
+            client_id: client_info.client_id.to_owned(),
+            client_secret: Some(client_info.client_secret.to_owned()),
+            scope: "email".to_owned(),
+            timeout: TIMEOUT,
+            ..Default::default()
+        };
+
+        get_token(args.to_owned(), browser).await.unwrap();
+
+        let active: serde_json::Value =
+            serde_json::from_str(&introspect(args.to_owned(), None).await.unwrap()).unwrap();
+        let inactive: serde_json::Value = serde_json::from_str(
+            &introspect(args, Some("not-a-token".to_owned()))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(active["active"], true);
+        assert_eq!(inactive["active"], false);
+    });
+}
+
+#[test]
+#[serial]
+fn it_revokes_the_cached_token() {
+    let _ = env_logger::try_init();
+    TOKIO_RUNTIME.block_on(async {
+        let idp_info = get_idp_info().await;
+
+        let browser = AUTH_BROWSER.clone();
+        let browser = browser.lock().await;
+        remove_config_if_available();
+        let client_info = idp_info.clients.first().unwrap();
+        let args = Arguments {
+            grant: Grant::ResourceOwnerPasswordClientCredentials,
+            discovery_url: Some(idp_info.discovery_url.to_owned()),
+            client_id: client_info.client_id.to_owned(),
+            client_secret: Some(client_info.client_secret.to_owned()),
+            username: Some(USERNAME.to_owned()),
+            password: Some(PASSWORD.to_owned()),
+            scope: "email".to_owned(),
+            timeout: TIMEOUT,
+            ..Default::default()
+        };
+
+        let token = get_token(args.to_owned(), browser).await.unwrap();
+        revoke(args.to_owned(), false).await.unwrap();
+
+        let introspection: serde_json::Value =
+            serde_json::from_str(&introspect(args.to_owned(), Some(token)).await.unwrap()).unwrap();
+
+        assert_eq!(introspection["active"], false);
+        assert!(introspect(args, None).await.is_err());
+    });
+}
+
+#[test]
+#[serial]
+fn it_fetches_userinfo_of_the_resource_owner() {
+    let _ = env_logger::try_init();
+    TOKIO_RUNTIME.block_on(async {
+        let idp_info = get_idp_info().await;
+
+        let browser = AUTH_BROWSER.clone();
+        let browser = browser.lock().await;
+        remove_config_if_available();
+        let client_info = idp_info.clients.first().unwrap();
+        let userinfo = get_userinfo(
+            Arguments {
+                grant: Grant::ResourceOwnerPasswordClientCredentials,
+                discovery_url: Some(idp_info.discovery_url.to_owned()),
+                client_id: client_info.client_id.to_owned(),
+                client_secret: Some(client_info.client_secret.to_owned()),
+                username: Some(USERNAME.to_owned()),
+                password: Some(PASSWORD.to_owned()),
+                scope: "openid email".to_owned(),
+                timeout: TIMEOUT,
+                ..Default::default()
+            },
+            browser,
+        )
+        .await
+        .unwrap();
+
+        let claims: serde_json::Value = serde_json::from_str(&userinfo).unwrap();
+
+        assert_eq!(claims["preferred_username"], USERNAME);
+    });
+}